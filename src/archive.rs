@@ -0,0 +1,175 @@
+// Sharing a whole directory behind a single link
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use zip::write::SimpleFileOptions;
+
+use crate::pipe::{self, PipeReader};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ArchiveFormat {
+    #[value(name = "tar.gz")]
+    TarGz,
+    #[value(name = "zip")]
+    Zip,
+}
+
+impl ArchiveFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+}
+
+// The archive is named after the directory, so `shuk --archive zip ./dist` uploads `dist.zip`
+pub fn archive_name(dir: &Path, format: ArchiveFormat) -> Result<String, anyhow::Error> {
+    Ok(format!("{}.{}", dir_name(dir)?, format.extension()))
+}
+
+// The name of the directory itself, which is also the top level folder inside the archive
+fn dir_name(dir: &Path) -> Result<String, anyhow::Error> {
+    if !dir.is_dir() {
+        return Err(anyhow::anyhow!("{:?} is not a directory", dir));
+    }
+    // canonicalize so `.` and `..` still get a proper name
+    dir.canonicalize()?
+        .file_name()
+        .and_then(|name| name.to_str())
+        .map(str::to_string)
+        .ok_or_else(|| anyhow::anyhow!("Unable to name an archive after {:?}", dir))
+}
+
+// Starts packing `dir` in the background and returns the stream of the archive. Errors while
+// packing (such as an unreadable file) surface as read errors, so the upload fails instead of
+// sharing a truncated archive.
+pub fn archive_reader(dir: &Path, format: ArchiveFormat) -> Result<PipeReader, anyhow::Error> {
+    let root_name = dir_name(dir)?;
+    let dir = dir.to_path_buf();
+    pipe::spawn_writer("archive", move |writer| match format {
+        ArchiveFormat::TarGz => write_tar_gz(writer, &dir, Path::new(&root_name)),
+        ArchiveFormat::Zip => write_zip(writer, &dir, Path::new(&root_name)),
+    })
+}
+
+fn write_tar_gz<W: Write>(writer: W, dir: &Path, root_name: &Path) -> io::Result<()> {
+    let mut builder = tar::Builder::new(GzEncoder::new(writer, Compression::default()));
+    builder.follow_symlinks(true);
+    builder.append_dir_all(root_name, dir)?;
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+fn write_zip<W: Write>(writer: W, dir: &Path, root_name: &Path) -> io::Result<()> {
+    let mut zip = zip::ZipWriter::new_stream(writer);
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+    for entry in walkdir::WalkDir::new(dir)
+        .follow_links(true)
+        .sort_by_file_name()
+    {
+        let entry = entry?;
+        let relative = entry.path().strip_prefix(dir).unwrap_or(entry.path());
+        // Zip entries always use `/`, whatever the local path separator is
+        let name = Path::new(root_name)
+            .join(relative)
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        if entry.file_type().is_dir() {
+            zip.add_directory(name, options).map_err(io::Error::other)?;
+        } else if entry.file_type().is_file() {
+            // Zip64 has to be decided before the entry is written, we can not go back and fix it
+            let large_file = entry.metadata()?.len() >= u32::MAX as u64;
+            zip.start_file(name, options.large_file(large_file))
+                .map_err(io::Error::other)?;
+            io::copy(&mut File::open(entry.path())?, &mut zip)?;
+        }
+    }
+
+    zip.finish().map_err(io::Error::other)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Read};
+
+    fn test_dir(name: &str) -> std::path::PathBuf {
+        let root = std::env::temp_dir()
+            .join(format!("shuk-archive-test-{}-{}", name, std::process::id()))
+            .join("site");
+        std::fs::create_dir_all(root.join("css")).unwrap();
+        std::fs::write(root.join("index.html"), "<html>").unwrap();
+        std::fs::write(root.join("css/site.css"), "body {}").unwrap();
+        root
+    }
+
+    #[test]
+    fn archives_are_named_after_the_directory() {
+        let root = test_dir("name");
+        assert_eq!(
+            archive_name(&root, ArchiveFormat::TarGz).unwrap(),
+            "site.tar.gz"
+        );
+        assert_eq!(
+            archive_name(&root.join("css/.."), ArchiveFormat::Zip).unwrap(),
+            "site.zip"
+        );
+        assert!(archive_name(&root.join("index.html"), ArchiveFormat::Zip).is_err());
+        std::fs::remove_dir_all(root.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn tar_gz_stream_contains_the_directory() {
+        let root = test_dir("tar");
+        let mut archive = Vec::new();
+        archive_reader(&root, ArchiveFormat::TarGz)
+            .unwrap()
+            .read_to_end(&mut archive)
+            .unwrap();
+
+        let mut tar = tar::Archive::new(flate2::read::GzDecoder::new(Cursor::new(archive)));
+        let mut names: Vec<String> = tar
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let path = entry.unwrap().path().unwrap().display().to_string();
+                path.trim_end_matches('/').to_string()
+            })
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec!["site", "site/css", "site/css/site.css", "site/index.html"]
+        );
+        std::fs::remove_dir_all(root.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn zip_stream_contains_the_directory() {
+        let root = test_dir("zip");
+        let mut archive = Vec::new();
+        archive_reader(&root, ArchiveFormat::Zip)
+            .unwrap()
+            .read_to_end(&mut archive)
+            .unwrap();
+
+        let mut zip = zip::ZipArchive::new(Cursor::new(archive)).unwrap();
+        let mut contents = String::new();
+        zip.by_name("site/css/site.css")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "body {}");
+        assert!(zip.by_name("site/index.html").is_ok());
+        std::fs::remove_dir_all(root.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,349 @@
+// End-to-end integrity checks with S3 checksums
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use aws_sdk_s3::types;
+use aws_smithy_types::base64;
+use crc_fast::CrcAlgorithm;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumAlgorithm {
+    Crc32c,
+    Crc64nvme,
+    Sha256,
+}
+
+impl ChecksumAlgorithm {
+    pub fn sdk_algorithm(&self) -> types::ChecksumAlgorithm {
+        match self {
+            ChecksumAlgorithm::Crc32c => types::ChecksumAlgorithm::Crc32C,
+            ChecksumAlgorithm::Crc64nvme => types::ChecksumAlgorithm::Crc64Nvme,
+            ChecksumAlgorithm::Sha256 => types::ChecksumAlgorithm::Sha256,
+        }
+    }
+
+    // S3 can only work out a checksum of the whole object from the part checksums for CRCs.
+    // SHA-256 multipart uploads get a checksum of the part checksums instead.
+    pub fn multipart_type(&self) -> types::ChecksumType {
+        match self {
+            ChecksumAlgorithm::Crc32c | ChecksumAlgorithm::Crc64nvme => {
+                types::ChecksumType::FullObject
+            }
+            ChecksumAlgorithm::Sha256 => types::ChecksumType::Composite,
+        }
+    }
+
+    // What to tell the user before a multipart upload with a checksum that is not of the whole
+    // object, and so can not be compared with `sha256sum` or the like
+    pub fn multipart_warning(&self) -> Option<String> {
+        if self.multipart_type() == types::ChecksumType::FullObject {
+            return None;
+        }
+        Some(format!(
+            "S3 can not keep a {} checksum of the whole object for a multipart upload. It stores a {} of the part checksums with a `-<parts>` suffix, which does not match a {} of the file. Use crc64nvme or crc32c for a checksum of the whole object.",
+            self.name(),
+            self.name(),
+            self.name()
+        ))
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Crc32c => "CRC32C",
+            ChecksumAlgorithm::Crc64nvme => "CRC64NVME",
+            ChecksumAlgorithm::Sha256 => "SHA-256",
+        }
+    }
+
+    fn crc_algorithm(&self) -> Option<CrcAlgorithm> {
+        match self {
+            ChecksumAlgorithm::Crc32c => Some(CrcAlgorithm::Crc32Iscsi),
+            ChecksumAlgorithm::Crc64nvme => Some(CrcAlgorithm::Crc64Nvme),
+            ChecksumAlgorithm::Sha256 => None,
+        }
+    }
+
+    // S3 expects CRCs as their big endian bytes
+    fn crc_bytes(&self, crc: u64) -> Vec<u8> {
+        match self {
+            ChecksumAlgorithm::Crc32c => (crc as u32).to_be_bytes().to_vec(),
+            _ => crc.to_be_bytes().to_vec(),
+        }
+    }
+}
+
+enum State {
+    Crc(crc_fast::Digest),
+    Sha256(Sha256),
+}
+
+pub struct Hasher {
+    algorithm: ChecksumAlgorithm,
+    state: State,
+}
+
+impl Hasher {
+    pub fn new(algorithm: ChecksumAlgorithm) -> Self {
+        let state = match algorithm.crc_algorithm() {
+            Some(crc) => State::Crc(crc_fast::Digest::new(crc)),
+            None => State::Sha256(Sha256::new()),
+        };
+        Self { algorithm, state }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match &mut self.state {
+            State::Crc(digest) => digest.update(data),
+            State::Sha256(digest) => digest.update(data),
+        }
+    }
+
+    pub fn finish(self) -> Checksum {
+        let bytes = match self.state {
+            State::Crc(digest) => self.algorithm.crc_bytes(digest.finalize()),
+            State::Sha256(digest) => digest.finalize().to_vec(),
+        };
+        Checksum {
+            algorithm: self.algorithm,
+            value: base64::encode(bytes),
+        }
+    }
+}
+
+// A checksum the way S3 writes it, base64 encoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checksum {
+    pub algorithm: ChecksumAlgorithm,
+    pub value: String,
+}
+
+impl Checksum {
+    // Every S3 request and response has a separate field per algorithm, these fill the right one
+    pub fn crc32c(&self) -> Option<String> {
+        self.value_for(ChecksumAlgorithm::Crc32c)
+    }
+
+    pub fn crc64nvme(&self) -> Option<String> {
+        self.value_for(ChecksumAlgorithm::Crc64nvme)
+    }
+
+    pub fn sha256(&self) -> Option<String> {
+        self.value_for(ChecksumAlgorithm::Sha256)
+    }
+
+    fn value_for(&self, algorithm: ChecksumAlgorithm) -> Option<String> {
+        (self.algorithm == algorithm).then(|| self.value.clone())
+    }
+
+    // Compares the checksum S3 reports for the object with this one. Both a missing checksum and
+    // a different one mean we can not vouch for what is in the bucket.
+    pub fn verify(
+        &self,
+        key: &str,
+        crc32c: Option<&str>,
+        crc64nvme: Option<&str>,
+        sha256: Option<&str>,
+    ) -> Result<(), anyhow::Error> {
+        let reported = match self.algorithm {
+            ChecksumAlgorithm::Crc32c => crc32c,
+            ChecksumAlgorithm::Crc64nvme => crc64nvme,
+            ChecksumAlgorithm::Sha256 => sha256,
+        };
+        match reported {
+            Some(reported) if reported == self.value => {
+                log::trace!(
+                    "{} checksum of {} verified: {}",
+                    self.algorithm.name(),
+                    key,
+                    reported
+                );
+                Ok(())
+            }
+            Some(reported) => Err(anyhow::anyhow!(
+                "CHECKSUM MISMATCH for {}: the local data has {} {}, but S3 stored {}. The object in the bucket is not the file you uploaded, do not share it",
+                key,
+                self.algorithm.name(),
+                self.value,
+                reported
+            )),
+            None => Err(anyhow::anyhow!(
+                "S3 did not return a {} checksum for {}, so the upload could not be verified",
+                self.algorithm.name(),
+                key
+            )),
+        }
+    }
+}
+
+pub fn checksum_bytes(algorithm: ChecksumAlgorithm, data: &[u8]) -> Checksum {
+    let mut hasher = Hasher::new(algorithm);
+    hasher.update(data);
+    hasher.finish()
+}
+
+// Checksum of `length` bytes of a file, starting at `offset`. This reads from disk, so call it from
+// a blocking task.
+pub fn checksum_file(
+    algorithm: ChecksumAlgorithm,
+    path: &Path,
+    offset: u64,
+    length: u64,
+) -> io::Result<Checksum> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut reader = file.take(length);
+    let mut hasher = Hasher::new(algorithm);
+    let mut buffer = vec![0; 1024 * 1024];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finish())
+}
+
+// The checksum S3 reports for a multipart upload, worked out from the part checksums and lengths
+// in part number order. CRCs of the parts combine into the CRC of the whole object, SHA-256 parts
+// end up as a checksum of the part checksums with the number of parts appended.
+pub fn multipart_checksum(
+    algorithm: ChecksumAlgorithm,
+    parts: &[(&str, u64)],
+) -> Result<Checksum, anyhow::Error> {
+    let decoded = parts
+        .iter()
+        .map(|(value, length)| {
+            base64::decode(value)
+                .map(|bytes| (bytes, *length))
+                .map_err(|error| anyhow::anyhow!("Invalid part checksum {:?}: {}", value, error))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let value = match algorithm.crc_algorithm() {
+        Some(crc) => {
+            let mut combined: Option<u64> = None;
+            for (bytes, length) in decoded {
+                let mut be_bytes = [0u8; 8];
+                be_bytes[8 - bytes.len()..].copy_from_slice(&bytes);
+                let part_crc = u64::from_be_bytes(be_bytes);
+                combined = Some(match combined {
+                    None => part_crc,
+                    Some(combined) => crc_fast::checksum_combine(crc, combined, part_crc, length),
+                });
+            }
+            let combined = combined.unwrap_or_else(|| crc_fast::checksum(crc, &[]));
+            base64::encode(algorithm.crc_bytes(combined))
+        }
+        None => {
+            let mut digest = Sha256::new();
+            for (bytes, _) in &decoded {
+                digest.update(bytes);
+            }
+            format!("{}-{}", base64::encode(digest.finalize()), decoded.len())
+        }
+    };
+    Ok(Checksum { algorithm, value })
+}
+
+// The part checksum that was sent with a part, whichever algorithm it was
+pub fn part_checksum(part: &types::CompletedPart) -> Option<&str> {
+    part.checksum_crc32_c()
+        .or(part.checksum_crc64_nvme())
+        .or(part.checksum_sha256())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crcs_match_the_reference_values() {
+        // The check values from the CRC catalogue, as S3 would encode them
+        assert_eq!(
+            checksum_bytes(ChecksumAlgorithm::Crc32c, b"123456789").value,
+            base64::encode(0xe3069283u32.to_be_bytes())
+        );
+        assert_eq!(
+            checksum_bytes(ChecksumAlgorithm::Crc64nvme, b"123456789").value,
+            base64::encode(0xae8b14860a799888u64.to_be_bytes())
+        );
+    }
+
+    #[test]
+    fn part_crcs_combine_into_the_full_object_crc() {
+        let data: Vec<u8> = (0..300_000).map(|i| (i % 251) as u8).collect();
+        for algorithm in [ChecksumAlgorithm::Crc32c, ChecksumAlgorithm::Crc64nvme] {
+            let parts: Vec<(Checksum, u64)> = data
+                .chunks(128 * 1024)
+                .map(|chunk| (checksum_bytes(algorithm, chunk), chunk.len() as u64))
+                .collect();
+            let parts: Vec<(&str, u64)> = parts
+                .iter()
+                .map(|(checksum, length)| (checksum.value.as_str(), *length))
+                .collect();
+            assert_eq!(
+                multipart_checksum(algorithm, &parts).unwrap(),
+                checksum_bytes(algorithm, &data)
+            );
+        }
+    }
+
+    #[test]
+    fn sha256_multipart_checksum_is_composite() {
+        let first = checksum_bytes(ChecksumAlgorithm::Sha256, b"first");
+        let second = checksum_bytes(ChecksumAlgorithm::Sha256, b"second");
+        let checksum = multipart_checksum(
+            ChecksumAlgorithm::Sha256,
+            &[(first.value.as_str(), 5), (second.value.as_str(), 6)],
+        )
+        .unwrap();
+
+        let mut digests = base64::decode(&first.value).unwrap();
+        digests.extend(base64::decode(&second.value).unwrap());
+        let expected = format!(
+            "{}-2",
+            checksum_bytes(ChecksumAlgorithm::Sha256, &digests).value
+        );
+        assert_eq!(checksum.value, expected);
+    }
+
+    #[test]
+    fn only_composite_checksums_warn_before_multipart_uploads() {
+        assert_eq!(ChecksumAlgorithm::Crc32c.multipart_warning(), None);
+        assert_eq!(ChecksumAlgorithm::Crc64nvme.multipart_warning(), None);
+        let warning = ChecksumAlgorithm::Sha256.multipart_warning().unwrap();
+        assert!(warning.contains("does not match a SHA-256 of the file"));
+    }
+
+    #[test]
+    fn file_ranges_are_checksummed() {
+        let path = std::env::temp_dir().join(format!("shuk-checksum-test-{}", std::process::id()));
+        std::fs::write(&path, b"0123456789").unwrap();
+        let checksum = checksum_file(ChecksumAlgorithm::Crc32c, &path, 2, 5).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            checksum,
+            checksum_bytes(ChecksumAlgorithm::Crc32c, b"23456")
+        );
+    }
+
+    #[test]
+    fn mismatches_fail_verification() {
+        let checksum = checksum_bytes(ChecksumAlgorithm::Crc32c, b"shuk");
+        assert!(checksum
+            .verify("key", Some(&checksum.value), None, None)
+            .is_ok());
+        let error = checksum
+            .verify("key", Some("AAAAAA=="), None, None)
+            .unwrap_err();
+        assert!(error.to_string().contains("CHECKSUM MISMATCH"));
+        assert!(checksum
+            .verify("key", None, None, Some(&checksum.value))
+            .is_err());
+    }
+}
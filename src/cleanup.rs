@@ -0,0 +1,201 @@
+// Cleaning up multipart uploads that are never going to be completed
+use std::sync::Mutex;
+
+use aws_sdk_s3::Client;
+use colored::Colorize;
+
+struct PendingUpload {
+    client: Client,
+    bucket: String,
+    key: String,
+    upload_id: String,
+    resumable: bool,
+}
+
+static PENDING: Mutex<Vec<PendingUpload>> = Mutex::new(Vec::new());
+
+fn pending() -> std::sync::MutexGuard<'static, Vec<PendingUpload>> {
+    // Nothing in here can be left half updated, so a poisoned lock is still usable
+    PENDING
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Keeps a multipart upload on the list until it is dropped, which happens once the upload has
+// finished one way or another
+pub struct Registration {
+    upload_id: String,
+}
+
+impl Registration {
+    // Aborts the upload after it failed. If Ctrl-C got to it first, there is nothing left to do.
+    pub async fn abort(self) {
+        let upload = {
+            let mut pending = pending();
+            pending
+                .iter()
+                .position(|upload| upload.upload_id == self.upload_id)
+                .map(|index| pending.remove(index))
+        };
+        if let Some(upload) = upload {
+            abort_upload(
+                &upload.client,
+                &upload.bucket,
+                &upload.key,
+                &upload.upload_id,
+            )
+            .await;
+        }
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        pending().retain(|upload| upload.upload_id != self.upload_id);
+    }
+}
+
+pub fn register(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+    resumable: bool,
+) -> Registration {
+    log::trace!(
+        "Registering multipart upload {} (resumable: {})",
+        &upload_id,
+        resumable
+    );
+    pending().push(PendingUpload {
+        client: client.clone(),
+        bucket: bucket.to_string(),
+        key: key.to_string(),
+        upload_id: upload_id.to_string(),
+        resumable,
+    });
+    Registration {
+        upload_id: upload_id.to_string(),
+    }
+}
+
+// Aborts a multipart upload, which deletes all of its parts, and tells the user about it. This is
+// cleanup on the way out, so a failure is reported, and only returned as `false`.
+pub async fn abort_upload(client: &Client, bucket: &str, key: &str, upload_id: &str) -> bool {
+    log::trace!("Aborting multipart upload {}", &upload_id);
+    match client
+        .abort_multipart_upload()
+        .bucket(bucket)
+        .key(key)
+        .upload_id(upload_id)
+        .send()
+        .await
+    {
+        Ok(_) => {
+            println!(
+                "🧹 | Aborted the unfinished multipart upload of s3://{}/{}, its parts were deleted",
+                bucket, key
+            );
+            true
+        }
+        Err(error) => {
+            println!(
+            "{} | Unable to abort the multipart upload of s3://{}/{} (upload ID {}), its parts stay in the bucket: {}",
+            "NOTE".yellow(),
+            bucket,
+            key,
+            upload_id,
+            crate::s3_error::S3OperationError::from_sdk_error(
+                "AbortMultipartUpload",
+                client,
+                bucket,
+                Some(key),
+                &error,
+            )
+        );
+            false
+        }
+    }
+}
+
+// Waits for Ctrl-C or SIGTERM in the background. When one arrives, every upload that is still
+// running is aborted (or kept, if it can be resumed) and shuk exits.
+pub fn handle_signals() {
+    tokio::spawn(async {
+        let exit_code = match wait_for_signal().await {
+            Ok(exit_code) => exit_code,
+            Err(error) => {
+                log::warn!("Unable to listen for Ctrl-C: {}", error);
+                return;
+            }
+        };
+        println!();
+        println!("========================================");
+        println!("🛑 | Interrupted");
+
+        let uploads = std::mem::take(&mut *pending());
+        for upload in uploads {
+            if upload.resumable {
+                println!(
+                    "⏯️  | Kept the unfinished upload of s3://{}/{}, run shuk again with the same file to resume it",
+                    upload.bucket, upload.key
+                );
+            } else {
+                abort_upload(
+                    &upload.client,
+                    &upload.bucket,
+                    &upload.key,
+                    &upload.upload_id,
+                )
+                .await;
+            }
+        }
+        std::process::exit(exit_code);
+    });
+}
+
+// Returns the exit code a shell would report for the signal
+#[cfg(unix)]
+async fn wait_for_signal() -> Result<i32, std::io::Error> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result.map(|()| 130),
+        _ = terminate.recv() => Ok(143),
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() -> Result<i32, std::io::Error> {
+    tokio::signal::ctrl_c().await.map(|()| 130)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client() -> Client {
+        let config = aws_sdk_s3::Config::builder()
+            .behavior_version_latest()
+            .region(aws_types::region::Region::new("us-east-1"))
+            .build();
+        Client::from_conf(config)
+    }
+
+    #[test]
+    fn uploads_leave_the_list_when_they_finish() {
+        let is_pending =
+            |upload_id: &str| pending().iter().any(|upload| upload.upload_id == upload_id);
+
+        let registration = register(&client(), "bucket", "key", "cleanup-test-1", false);
+        let other = register(&client(), "bucket", "key", "cleanup-test-2", true);
+        assert!(is_pending("cleanup-test-1"));
+
+        drop(registration);
+        assert!(!is_pending("cleanup-test-1"));
+        assert!(is_pending("cleanup-test-2"));
+        drop(other);
+        assert!(!is_pending("cleanup-test-2"));
+    }
+}
@@ -0,0 +1,114 @@
+// Transparent compression of uploads
+use std::io::{self, Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::encryption;
+use crate::pipe::{self, PipeReader};
+use crate::utils;
+
+// zstd's own default, a good balance between speed and size for logs and JSON
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    // The `Content-Encoding` header for the compressed object
+    pub fn content_encoding(&self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip => Some("gzip"),
+            Compression::Zstd => Some("zstd"),
+        }
+    }
+}
+
+// The compression to use for an upload. Encrypted objects are opaque `.age` files that nothing
+// would decompress, so they are never compressed.
+pub fn for_upload(shuk_config: &utils::Config) -> Compression {
+    let compression = shuk_config.compress.unwrap_or_default();
+    if compression != Compression::None && encryption::is_enabled(shuk_config) {
+        log::warn!("Encrypted uploads are not compressed, ignoring compress = {compression:?}");
+        return Compression::None;
+    }
+    compression
+}
+
+// Compresses `reader` on a separate thread and returns the compressed stream
+pub fn compress_reader<R: Read + Send + 'static>(
+    mut reader: R,
+    compression: Compression,
+) -> Result<PipeReader, anyhow::Error> {
+    pipe::spawn_writer("compress", move |writer| {
+        match compression {
+            Compression::None => {
+                io::copy(&mut reader, writer)?;
+            }
+            Compression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(writer, flate2::Compression::default());
+                io::copy(&mut reader, &mut encoder)?;
+                encoder.finish()?;
+            }
+            Compression::Zstd => {
+                let mut encoder = zstd::stream::write::Encoder::new(writer, ZSTD_LEVEL)?;
+                io::copy(&mut reader, &mut encoder)?;
+                encoder.finish()?.flush()?;
+            }
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compress(data: &[u8], compression: Compression) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        compress_reader(io::Cursor::new(data.to_vec()), compression)
+            .unwrap()
+            .read_to_end(&mut compressed)
+            .unwrap();
+        compressed
+    }
+
+    #[test]
+    fn gzip_round_trips() {
+        let data = "{\"level\":\"info\",\"msg\":\"hello\"}\n".repeat(10_000);
+        let compressed = compress(data.as_bytes(), Compression::Gzip);
+        assert!(compressed.len() < data.len());
+
+        let mut decompressed = String::new();
+        flate2::read::GzDecoder::new(compressed.as_slice())
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, data);
+    }
+
+    #[test]
+    fn zstd_round_trips() {
+        let data = "{\"level\":\"info\",\"msg\":\"hello\"}\n".repeat(10_000);
+        let compressed = compress(data.as_bytes(), Compression::Zstd);
+        assert!(compressed.len() < data.len());
+        assert_eq!(
+            zstd::decode_all(compressed.as_slice()).unwrap(),
+            data.as_bytes()
+        );
+    }
+
+    #[test]
+    fn encrypted_uploads_are_not_compressed() {
+        let mut config = utils::Config::from_toml("compress = \"zstd\"");
+        assert_eq!(for_upload(&config), Compression::Zstd);
+        config.encrypt = Some(true);
+        assert_eq!(for_upload(&config), Compression::None);
+    }
+}
@@ -0,0 +1,196 @@
+// What to do when a different file is already at the key
+use std::fmt;
+use std::io::{self, IsTerminal, Write};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::utils;
+
+// Exit statuses. 1 is any other error, and 2 is what clap uses for invalid arguments.
+pub const EXIT_CONFLICT_FAILED: i32 = 3;
+pub const EXIT_CONFLICT_SKIPPED: i32 = 4;
+pub const EXIT_CONFLICT_RENAMED: i32 = 5;
+// Only with `on_conflict = "overwrite"` configured, overwriting without it exits with 0 as always
+pub const EXIT_CONFLICT_OVERWRITTEN: i32 = 6;
+
+// How many numbered names are tried before a timestamp is used
+pub const MAX_NUMBERED_NAMES: u32 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum OnConflict {
+    Overwrite,
+    Fail,
+    Prompt,
+    Rename,
+    Skip,
+}
+
+impl fmt::Display for OnConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            OnConflict::Overwrite => "overwrite",
+            OnConflict::Fail => "fail",
+            OnConflict::Prompt => "prompt",
+            OnConflict::Rename => "rename",
+            OnConflict::Skip => "skip",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// What is done about one conflict, once a prompt has been answered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    Overwrite,
+    // `no_terminal` when the policy was to prompt, but there was no terminal to ask on
+    Fail { no_terminal: bool },
+    Rename,
+    Skip,
+}
+
+// What to do about the different object at `uri`. With `on_conflict = "prompt"` this asks.
+pub fn decide(shuk_config: &utils::Config, uri: &str) -> Result<Resolution, anyhow::Error> {
+    resolve(shuk_config.on_conflict, io::stdin().is_terminal(), || {
+        ask(uri)
+    })
+}
+
+fn resolve(
+    on_conflict: Option<OnConflict>,
+    terminal: bool,
+    ask: impl FnOnce() -> Result<Resolution, anyhow::Error>,
+) -> Result<Resolution, anyhow::Error> {
+    match on_conflict {
+        None | Some(OnConflict::Overwrite) => Ok(Resolution::Overwrite),
+        Some(OnConflict::Fail) => Ok(Resolution::Fail { no_terminal: false }),
+        Some(OnConflict::Prompt) if !terminal => Ok(Resolution::Fail { no_terminal: true }),
+        Some(OnConflict::Prompt) => ask(),
+        Some(OnConflict::Rename) => Ok(Resolution::Rename),
+        Some(OnConflict::Skip) => Ok(Resolution::Skip),
+    }
+}
+
+fn ask(uri: &str) -> Result<Resolution, anyhow::Error> {
+    loop {
+        print!(
+            "❓ | {} holds a different file. [o]verwrite, [r]ename, [s]kip or [f]ail? ",
+            uri
+        );
+        io::stdout().flush()?; // so the answer is typed on the same line as above
+        let mut answer = String::new();
+        if io::stdin().read_line(&mut answer)? == 0 {
+            return Ok(Resolution::Fail { no_terminal: false });
+        }
+        if let Some(resolution) = parse_answer(&answer) {
+            return Ok(resolution);
+        }
+    }
+}
+
+fn parse_answer(answer: &str) -> Option<Resolution> {
+    match answer.trim().to_ascii_lowercase().as_str() {
+        "o" | "overwrite" => Some(Resolution::Overwrite),
+        "r" | "rename" => Some(Resolution::Rename),
+        "s" | "skip" => Some(Resolution::Skip),
+        "f" | "fail" => Some(Resolution::Fail { no_terminal: false }),
+        _ => None,
+    }
+}
+
+// Where the suffix of a renamed file goes: before the extension of the last part of the name, and
+// before `.tar` of an archive
+fn split_name(name: &str) -> (&str, &str) {
+    let file_start = name.rfind('/').map_or(0, |slash| slash + 1);
+    let file_name = &name[file_start..];
+    let dot = match file_name.rfind('.') {
+        Some(dot) if dot > 0 => {
+            let stem = &file_name[..dot];
+            match stem.strip_suffix(".tar") {
+                Some(tar_stem) if !tar_stem.is_empty() => tar_stem.len(),
+                _ => dot,
+            }
+        }
+        _ => file_name.len(),
+    };
+    name.split_at(file_start + dot)
+}
+
+// `notes.txt` as `notes-2.txt`
+pub fn numbered_name(name: &str, number: u32) -> String {
+    let (stem, extension) = split_name(name);
+    format!("{}-{}{}", stem, number, extension)
+}
+
+// `notes.txt` as `notes-20261017T093000.txt`, for when all the numbered names are taken
+pub fn timestamped_name(name: &str, now: DateTime<Local>) -> String {
+    let (stem, extension) = split_name(name);
+    format!("{}-{}{}", stem, now.format("%Y%m%dT%H%M%S"), extension)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn renamed_files_keep_their_extension() {
+        assert_eq!(numbered_name("notes.txt", 1), "notes-1.txt");
+        assert_eq!(numbered_name("docs/v1.2/notes", 2), "docs/v1.2/notes-2");
+        assert_eq!(numbered_name(".bashrc", 3), ".bashrc-3");
+        assert_eq!(numbered_name("dist.tar.zst", 1), "dist-1.tar.zst");
+        assert_eq!(numbered_name("my.report.pdf", 1), "my.report-1.pdf");
+        assert_eq!(
+            timestamped_name(
+                "notes.txt",
+                Local.with_ymd_and_hms(2026, 10, 17, 9, 30, 0).unwrap()
+            ),
+            "notes-20261017T093000.txt"
+        );
+    }
+
+    #[test]
+    fn answers_pick_a_policy() {
+        assert_eq!(parse_answer("o\n"), Some(Resolution::Overwrite));
+        assert_eq!(parse_answer(" Rename "), Some(Resolution::Rename));
+        assert_eq!(parse_answer("S"), Some(Resolution::Skip));
+        assert_eq!(
+            parse_answer("fail"),
+            Some(Resolution::Fail { no_terminal: false })
+        );
+        assert_eq!(parse_answer("prompt"), None);
+        assert_eq!(parse_answer(""), None);
+    }
+
+    #[test]
+    fn the_configured_policy_is_used() {
+        let mut shuk_config = utils::Config::from_toml("on_conflict = \"skip\"");
+        assert_eq!(
+            decide(&shuk_config, "s3://foo/shuk/notes.txt").unwrap(),
+            Resolution::Skip
+        );
+        shuk_config.on_conflict = None;
+        assert_eq!(
+            decide(&shuk_config, "s3://foo/shuk/notes.txt").unwrap(),
+            Resolution::Overwrite
+        );
+    }
+
+    #[test]
+    fn prompts_without_a_terminal_fail() {
+        let answered = || Ok(Resolution::Rename);
+        assert_eq!(
+            resolve(Some(OnConflict::Prompt), true, answered).unwrap(),
+            Resolution::Rename
+        );
+        assert_eq!(
+            resolve(Some(OnConflict::Prompt), false, answered).unwrap(),
+            Resolution::Fail { no_terminal: true }
+        );
+        assert_eq!(
+            resolve(Some(OnConflict::Fail), false, answered).unwrap(),
+            Resolution::Fail { no_terminal: false }
+        );
+    }
+}
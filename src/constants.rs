@@ -1,12 +1,17 @@
 // CONFIGURATION FILES
 pub static CONFIG_DIR_NAME: &str = "shuk";
 pub static CONFIG_FILE_NAME: &str = "shuk.toml";
+// Journals for multipart uploads that can be resumed live in this subdirectory of CONFIG_DIR_NAME
+pub static JOURNAL_DIR_NAME: &str = "uploads";
+// Which random key a file got with `unguessable_keys`, see `unguessable`
+pub static KEY_DIR_NAME: &str = "keys";
 
-// UPDATED: 2024-04-20
+// UPDATED: 2026-10-17
 pub static CONFIG_FILE: &str = r#"bucket_name = "foo"
 bucket_prefix = "bar"
 presigned_time = 86400
 aws_profile = "default"
 use_clipboard = false
 fallback_region = "us-east-1"
+upload_concurrency = 4
 "#;
@@ -0,0 +1,138 @@
+// Content addressed object keys
+use std::path::Path;
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+
+use crate::{file_management, key_template, unguessable, utils};
+
+// The metadata with the name of the file the object was uploaded as
+pub const FILE_NAME_METADATA: &str = "shuk-file-name";
+// Longer names are cut short, so the metadata always fits next to the `[metadata]` of the
+// configuration
+const MAX_FILE_NAME_SIZE: usize = 512;
+pub const RESERVED_METADATA_SIZE: usize = FILE_NAME_METADATA.len() + MAX_FILE_NAME_SIZE;
+
+// Everything but printable ASCII, and `%` so the name can be decoded again
+const FILE_NAME_ESCAPE: &AsciiSet = &CONTROLS.add(b'%');
+
+pub fn is_enabled(shuk_config: &utils::Config) -> bool {
+    shuk_config.content_addressed == Some(true)
+}
+
+// Content addressing names the object itself, so no other key mode can be on as well
+pub fn check(shuk_config: &utils::Config) -> Result<(), anyhow::Error> {
+    if !is_enabled(shuk_config) {
+        return Ok(());
+    }
+    if shuk_config.key_template.is_some() || unguessable::is_enabled(shuk_config) {
+        return Err(anyhow::anyhow!(
+            "content_addressed decides on the object keys itself, it can not be combined with key_template or unguessable_keys"
+        ));
+    }
+    Ok(())
+}
+
+// The key name of a local file called `name`: the SHA-256 of its contents, or what the key template
+// makes of the name
+pub fn object_name(
+    shuk_config: &utils::Config,
+    name: &str,
+    local_path: &Path,
+) -> Result<String, anyhow::Error> {
+    if !is_enabled(shuk_config) {
+        return key_template::object_name(shuk_config, name, Some(local_path));
+    }
+    let hash = file_management::calculate_full_hash(local_path)?;
+    log::trace!("{:?} is stored by its SHA-256: {}", local_path, hash);
+    Ok(hash)
+}
+
+// The `x-amz-meta-shuk-file-name` of an object, if keys are content addressed
+pub fn name_metadata(shuk_config: &utils::Config, name: &str) -> Option<(String, String)> {
+    if !is_enabled(shuk_config) {
+        return None;
+    }
+    let file_name = name.rsplit('/').next().unwrap_or(name);
+    Some((FILE_NAME_METADATA.to_string(), encode_file_name(file_name)))
+}
+
+// Percent encodes the name character by character, so a name that is cut short never ends in the
+// middle of one
+fn encode_file_name(file_name: &str) -> String {
+    let mut encoded = String::new();
+    let mut buffer = [0; 4];
+    for c in file_name.chars() {
+        let piece = utf8_percent_encode(c.encode_utf8(&mut buffer), FILE_NAME_ESCAPE).to_string();
+        if encoded.len() + piece.len() > MAX_FILE_NAME_SIZE {
+            break;
+        }
+        encoded.push_str(&piece);
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_with_the_same_contents_share_a_key() {
+        let dir = std::env::temp_dir().join(format!("shuk-content-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("ubuntu.iso"), b"hello").unwrap();
+        std::fs::write(dir.join("install.iso"), b"hello").unwrap();
+        let shuk_config = utils::Config::from_toml("content_addressed = true");
+        let first = object_name(&shuk_config, "ubuntu.iso", &dir.join("ubuntu.iso"));
+        let second = object_name(&shuk_config, "install.iso", &dir.join("install.iso"));
+        let plain = object_name(
+            &utils::Config::from_toml(""),
+            "install.iso",
+            &dir.join("install.iso"),
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // The SHA-256 of "hello"
+        assert_eq!(
+            first.unwrap(),
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        assert_eq!(
+            second.unwrap(),
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        assert_eq!(plain.unwrap(), "install.iso");
+    }
+
+    #[test]
+    fn file_names_are_kept_as_ascii_metadata() {
+        let shuk_config = utils::Config::from_toml("content_addressed = true");
+        assert_eq!(
+            name_metadata(&utils::Config::from_toml(""), "report.pdf"),
+            None
+        );
+        assert_eq!(
+            name_metadata(&shuk_config, "docs/Grüße 100%.txt"),
+            Some((
+                "shuk-file-name".to_string(),
+                "Gr%C3%BC%C3%9Fe 100%25.txt".to_string()
+            ))
+        );
+        let (_, long) = name_metadata(&shuk_config, &"ü".repeat(300)).unwrap();
+        assert_eq!(long.len(), 510);
+        assert!(long.ends_with("%C3%BC"));
+    }
+
+    #[test]
+    fn other_key_settings_are_rejected() {
+        check(&utils::Config::from_toml("content_addressed = true")).unwrap();
+        check(&utils::Config::from_toml("key_template = \"{uuid}\"")).unwrap();
+        assert!(check(&utils::Config::from_toml(
+            "content_addressed = true\nkey_template = \"{uuid}\""
+        ))
+        .is_err());
+        assert!(check(&utils::Config::from_toml(
+            "content_addressed = true\nunguessable_keys = true"
+        ))
+        .is_err());
+    }
+}
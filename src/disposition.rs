@@ -0,0 +1,165 @@
+// Content-Disposition for shared objects
+use serde::{Deserialize, Serialize};
+
+use crate::{content_addressed, unguessable, utils};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Disposition {
+    // Always download the file
+    Attachment,
+    // Show the file in the browser if it can, download it otherwise
+    Inline,
+}
+
+impl Disposition {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Disposition::Attachment => "attachment",
+            Disposition::Inline => "inline",
+        }
+    }
+}
+
+// Random keys and content hashes say nothing about the file, only its own name does
+fn keys_are_opaque(shuk_config: &utils::Config) -> bool {
+    unguessable::is_enabled(shuk_config) || content_addressed::is_enabled(shuk_config)
+}
+
+// The name a download is saved as and its Content-Type is told by: the key name, or `name` if the
+// key does not say what is inside
+pub fn download_name<'a>(shuk_config: &utils::Config, key_name: &'a str, name: &'a str) -> &'a str {
+    if keys_are_opaque(shuk_config) {
+        name
+    } else {
+        key_name
+    }
+}
+
+// The header for a file called `name`, if a disposition is configured. Only the last part of the
+// name is used, the folders in front of it are not part of a download's file name.
+pub fn for_object(shuk_config: &utils::Config, name: &str) -> Option<String> {
+    let disposition = match shuk_config.content_disposition {
+        Some(disposition) => disposition,
+        // A random key or a hash is no name to save a download as
+        None if keys_are_opaque(shuk_config) => Disposition::Attachment,
+        None => return None,
+    };
+    let file_name = name.rsplit('/').next().unwrap_or(name);
+    Some(header_value(disposition, file_name))
+}
+
+// `response-content-disposition` for a presigned URL, for a download called `name`. With
+// unguessable keys the object already has the header with the original name, which the URL would
+// give away. With content addressed keys every file with the same content shares the object, and
+// the URL names the download after the file that is shared this time.
+pub fn for_presign(shuk_config: &utils::Config, name: &str) -> Option<String> {
+    if unguessable::is_enabled(shuk_config) {
+        return None;
+    }
+    for_object(shuk_config, name)
+}
+
+// `filename` is for older clients and only holds ASCII, so a name with anything else in it also
+// gets an RFC 5987 encoded `filename*` with the real name, which every current browser prefers
+pub fn header_value(disposition: Disposition, file_name: &str) -> String {
+    let fallback: String = file_name
+        .chars()
+        .map(|c| {
+            if c == ' ' || (c.is_ascii_graphic() && c != '"' && c != '\\') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if fallback == file_name {
+        format!("{}; filename=\"{}\"", disposition.as_str(), file_name)
+    } else {
+        format!(
+            "{}; filename=\"{}\"; filename*=UTF-8''{}",
+            disposition.as_str(),
+            fallback,
+            rfc5987_encode(file_name)
+        )
+    }
+}
+
+// Percent encodes everything but the `attr-char`s of RFC 5987
+fn rfc5987_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii_names_are_quoted() {
+        assert_eq!(
+            header_value(Disposition::Attachment, "Q3 report.pdf"),
+            "attachment; filename=\"Q3 report.pdf\""
+        );
+        assert_eq!(
+            header_value(Disposition::Inline, "photo.jpg"),
+            "inline; filename=\"photo.jpg\""
+        );
+    }
+
+    #[test]
+    fn other_names_are_rfc5987_encoded() {
+        assert_eq!(
+            header_value(Disposition::Attachment, "Grüße €.txt"),
+            "attachment; filename=\"Gr__e _.txt\"; filename*=UTF-8''Gr%C3%BC%C3%9Fe%20%E2%82%AC.txt"
+        );
+        // Quotes would end the plain `filename` early
+        assert_eq!(
+            header_value(Disposition::Inline, "say \"hi\".txt"),
+            "inline; filename=\"say _hi_.txt\"; filename*=UTF-8''say%20%22hi%22.txt"
+        );
+    }
+
+    #[test]
+    fn only_the_file_name_is_used() {
+        let mut config = utils::Config::from_toml("");
+        assert_eq!(for_object(&config, "docs/report.pdf"), None);
+
+        config.content_disposition = Some(Disposition::Attachment);
+        assert_eq!(
+            for_object(&config, "docs/report.pdf").as_deref(),
+            Some("attachment; filename=\"report.pdf\"")
+        );
+    }
+
+    #[test]
+    fn unguessable_keys_keep_the_name_out_of_the_url() {
+        let mut config = utils::Config::from_toml("unguessable_keys = true");
+        assert_eq!(
+            for_object(&config, "report.pdf").as_deref(),
+            Some("attachment; filename=\"report.pdf\"")
+        );
+        assert_eq!(for_presign(&config, "shuk/0f3a"), None);
+
+        config.content_disposition = Some(Disposition::Inline);
+        assert_eq!(
+            for_object(&config, "report.pdf").as_deref(),
+            Some("inline; filename=\"report.pdf\"")
+        );
+        assert_eq!(for_presign(&config, "shuk/0f3a"), None);
+        assert_eq!(download_name(&config, "0f3a", "report.pdf"), "report.pdf");
+
+        config.unguessable_keys = None;
+        assert_eq!(
+            download_name(&config, "docs/report.pdf", "report.pdf"),
+            "docs/report.pdf"
+        );
+    }
+}
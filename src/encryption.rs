@@ -0,0 +1,308 @@
+// Client-side encryption with age (https://age-encryption.org)
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Write};
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use age::secrecy::{ExposeSecret, SecretString};
+
+use crate::pipe::{self, PipeReader};
+use crate::utils;
+
+// Encrypted objects get this extension on top of their own, so `report.pdf` becomes
+// `report.pdf.age`
+pub const ENCRYPTED_EXTENSION: &str = "age";
+// Set this to skip the passphrase prompt, for example in scripts
+pub const PASSPHRASE_ENV: &str = "SHUK_PASSPHRASE";
+
+// The passphrase is asked for once per run, no matter how many files are encrypted with it
+static PASSPHRASE: OnceLock<SecretString> = OnceLock::new();
+
+pub fn is_enabled(shuk_config: &utils::Config) -> bool {
+    shuk_config.encrypt.unwrap_or(false)
+}
+
+// The name the object gets in the bucket
+pub fn object_name(shuk_config: &utils::Config, name: &str) -> String {
+    if is_enabled(shuk_config) {
+        format!("{}.{}", name, ENCRYPTED_EXTENSION)
+    } else {
+        name.to_string()
+    }
+}
+
+fn read_passphrase(confirm: bool) -> Result<SecretString, anyhow::Error> {
+    if let Ok(passphrase) = env::var(PASSPHRASE_ENV) {
+        log::trace!("Using the passphrase from {}", PASSPHRASE_ENV);
+        return Ok(passphrase.into());
+    }
+    if let Some(passphrase) = PASSPHRASE.get() {
+        return Ok(passphrase.expose_secret().to_owned().into());
+    }
+
+    // This reads from the terminal, not stdin, so it also works while stdin is being uploaded
+    let passphrase = rpassword::prompt_password("🔑 | Passphrase: ")?;
+    if passphrase.is_empty() {
+        return Err(anyhow::anyhow!("The passphrase can not be empty"));
+    }
+    if confirm && rpassword::prompt_password("🔑 | Confirm passphrase: ")? != passphrase {
+        return Err(anyhow::anyhow!("The passphrases do not match"));
+    }
+    let passphrase = PASSPHRASE.get_or_init(|| passphrase.into());
+    Ok(passphrase.expose_secret().to_owned().into())
+}
+
+fn parse_recipient(recipient: &str) -> Result<Box<dyn age::Recipient + Send>, anyhow::Error> {
+    if let Ok(recipient) = recipient.parse::<age::x25519::Recipient>() {
+        return Ok(Box::new(recipient));
+    }
+    if let Ok(recipient) = recipient.parse::<age::ssh::Recipient>() {
+        return Ok(Box::new(recipient));
+    }
+    Err(anyhow::anyhow!(
+        "{:?} is not an age (age1...) or SSH public key",
+        recipient
+    ))
+}
+
+// Every encrypted object needs its own encryptor, so each one gets a fresh file key
+fn encryptor(shuk_config: &utils::Config) -> Result<age::Encryptor, anyhow::Error> {
+    let recipients = shuk_config
+        .encryption_recipients
+        .as_deref()
+        .unwrap_or_default();
+    if recipients.is_empty() {
+        log::trace!("No recipients are configured, encrypting with a passphrase");
+        return Ok(age::Encryptor::with_user_passphrase(read_passphrase(true)?));
+    }
+
+    let recipients = recipients
+        .iter()
+        .map(|recipient| parse_recipient(recipient))
+        .collect::<Result<Vec<_>, _>>()?;
+    log::trace!("Encrypting to {} recipients", recipients.len());
+    Ok(age::Encryptor::with_recipients(recipients.iter().map(
+        |recipient| recipient.as_ref() as &dyn age::Recipient,
+    ))?)
+}
+
+// Encrypts `reader` on a separate thread and returns the encrypted stream
+pub fn encrypt_reader<R: Read + Send + 'static>(
+    mut reader: R,
+    shuk_config: &utils::Config,
+) -> Result<PipeReader, anyhow::Error> {
+    let encryptor = encryptor(shuk_config)?;
+    pipe::spawn_writer("encrypt", move |writer| {
+        let mut encrypted = encryptor.wrap_output(writer)?;
+        io::copy(&mut reader, &mut encrypted)?;
+        // Without this the last chunk is never written and the file can not be decrypted
+        encrypted.finish()?;
+        Ok(())
+    })
+}
+
+// Reads the private keys to decrypt with. Both age identity files and unencrypted SSH private
+// keys work.
+fn load_identities(path: &Path) -> Result<Vec<Box<dyn age::Identity>>, anyhow::Error> {
+    let contents = fs::read_to_string(path)
+        .map_err(|error| anyhow::anyhow!("Unable to read identity file {:?}: {}", path, error))?;
+
+    if contents.contains("-----BEGIN") {
+        let identity =
+            age::ssh::Identity::from_buffer(contents.as_bytes(), Some(path.display().to_string()))?;
+        return match identity {
+            age::ssh::Identity::Unencrypted(_) => Ok(vec![Box::new(identity)]),
+            age::ssh::Identity::Encrypted(_) => Err(anyhow::anyhow!(
+                "{:?} is protected with a password, which shuk does not support. Use an age identity or an unencrypted SSH key",
+                path
+            )),
+            age::ssh::Identity::Unsupported(_) => Err(anyhow::anyhow!(
+                "{:?} is not a supported SSH key type",
+                path
+            )),
+        };
+    }
+
+    let identities = age::IdentityFile::from_buffer(contents.as_bytes())?.into_identities()?;
+    if identities.is_empty() {
+        return Err(anyhow::anyhow!(
+            "{:?} does not contain any age identities",
+            path
+        ));
+    }
+    Ok(identities)
+}
+
+// Decrypts everything from `input` into `output` and returns the number of bytes written.
+// Passphrase encrypted data asks for the passphrase, anything else needs an identity file.
+pub fn decrypt<R: Read>(
+    input: R,
+    output: &mut dyn Write,
+    identity_file: Option<&Path>,
+) -> Result<u64, anyhow::Error> {
+    let decryptor = age::Decryptor::new_buffered(BufReader::new(input))
+        .map_err(|error| anyhow::anyhow!("This is not a file encrypted by shuk: {}", error))?;
+    let mut reader = if decryptor.is_scrypt() {
+        log::trace!("The data is encrypted with a passphrase");
+        let identity = age::scrypt::Identity::new(read_passphrase(false)?);
+        decryptor.decrypt(iter::once(&identity as &dyn age::Identity))?
+    } else {
+        let identity_file = identity_file.ok_or_else(|| {
+            anyhow::anyhow!(
+                "This file is encrypted to a public key. Pass the matching private key with --identity, or set identity_file in the configuration"
+            )
+        })?;
+        let identities = load_identities(identity_file)?;
+        decryptor.decrypt(identities.iter().map(|identity| identity.as_ref()))?
+    };
+    Ok(io::copy(&mut reader, output)?)
+}
+
+// Where `shuk decrypt` writes to when no output is given: the input without its `.age` extension,
+// or stdout when the input is stdin
+fn default_output(input: &Path) -> Result<PathBuf, anyhow::Error> {
+    if input == Path::new("-") {
+        return Ok(input.to_path_buf());
+    }
+    if input
+        .extension()
+        .is_some_and(|extension| extension == ENCRYPTED_EXTENSION)
+    {
+        Ok(input.with_extension(""))
+    } else {
+        Err(anyhow::anyhow!(
+            "{:?} does not end in .{}, pass --output to say where the decrypted file should go",
+            input,
+            ENCRYPTED_EXTENSION
+        ))
+    }
+}
+
+// `shuk decrypt`. An existing file is never overwritten, and if decryption fails half way nothing
+// is left behind at the output path.
+pub fn decrypt_file(
+    input: &Path,
+    output: Option<&Path>,
+    identity_file: Option<&Path>,
+) -> Result<(), anyhow::Error> {
+    let output = match output {
+        Some(output) => output.to_path_buf(),
+        None => default_output(input)?,
+    };
+    log::trace!("Decrypting {:?} into {:?}", &input, &output);
+
+    let reader: Box<dyn Read> = if input == Path::new("-") {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(
+            File::open(input)
+                .map_err(|error| anyhow::anyhow!("Unable to open {:?}: {}", input, error))?,
+        )
+    };
+
+    if output == Path::new("-") {
+        let mut stdout = io::stdout().lock();
+        decrypt(reader, &mut stdout, identity_file)?;
+        stdout.flush()?;
+        return Ok(());
+    }
+
+    if output.try_exists()? {
+        return Err(anyhow::anyhow!(
+            "{:?} already exists, pass --output to write the decrypted file somewhere else",
+            output
+        ));
+    }
+    let mut temp_path = output.clone().into_os_string();
+    temp_path.push(".part");
+    let temp_path = PathBuf::from(temp_path);
+
+    let result = File::create(&temp_path)
+        .map_err(anyhow::Error::from)
+        .and_then(|mut file| decrypt(reader, &mut file, identity_file));
+    match result {
+        Ok(size) => {
+            fs::rename(&temp_path, &output)?;
+            println!("========================================");
+            println!(
+                "🔓 | Decrypted {:?} to {:?} ({})",
+                input,
+                output,
+                indicatif::HumanBytes(size)
+            );
+            Ok(())
+        }
+        Err(error) => {
+            let _ = fs::remove_file(&temp_path);
+            Err(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use age::secrecy::ExposeSecret;
+
+    fn config(recipients: Option<Vec<String>>) -> utils::Config {
+        let mut config = utils::Config::from_toml("encrypt = true");
+        config.encryption_recipients = recipients;
+        config
+    }
+
+    #[test]
+    fn encrypted_objects_get_the_age_extension() {
+        assert_eq!(object_name(&config(None), "report.pdf"), "report.pdf.age");
+        let mut plain = config(None);
+        plain.encrypt = None;
+        assert_eq!(object_name(&plain, "report.pdf"), "report.pdf");
+    }
+
+    #[test]
+    fn round_trips_with_a_recipient_key() {
+        let identity = age::x25519::Identity::generate();
+        let recipient = identity.to_public().to_string();
+        let plaintext: Vec<u8> = (0..200_000).map(|i| (i % 251) as u8).collect();
+
+        let mut encrypted = Vec::new();
+        encrypt_reader(
+            io::Cursor::new(plaintext.clone()),
+            &config(Some(vec![recipient])),
+        )
+        .unwrap()
+        .read_to_end(&mut encrypted)
+        .unwrap();
+        assert_ne!(encrypted, plaintext);
+
+        let identity_path =
+            std::env::temp_dir().join(format!("shuk-identity-test-{}.txt", std::process::id()));
+        fs::write(&identity_path, identity.to_string().expose_secret()).unwrap();
+        let mut decrypted = Vec::new();
+        decrypt(
+            encrypted.as_slice(),
+            &mut decrypted,
+            Some(identity_path.as_path()),
+        )
+        .unwrap();
+        fs::remove_file(identity_path).unwrap();
+        assert_eq!(decrypted, plaintext);
+    }
+
+    #[test]
+    fn decrypted_files_drop_the_age_extension() {
+        assert_eq!(
+            default_output(Path::new("dir/report.pdf.age")).unwrap(),
+            PathBuf::from("dir/report.pdf")
+        );
+        assert_eq!(default_output(Path::new("-")).unwrap(), PathBuf::from("-"));
+        assert!(default_output(Path::new("report.pdf")).is_err());
+    }
+
+    #[test]
+    fn invalid_recipients_are_rejected() {
+        let config = config(Some(vec!["not-a-key".into()]));
+        assert!(encrypt_reader(io::empty(), &config).is_err());
+    }
+}
@@ -1,30 +1,41 @@
 use aws_sdk_s3::error::SdkError;
-use aws_sdk_s3::operation::head_object::HeadObjectError;
+use aws_sdk_s3::operation::head_object::{HeadObjectError, HeadObjectOutput};
 use aws_sdk_s3::presigning::PresigningConfig;
+use aws_sdk_s3::types::{Tag, Tagging};
 use aws_sdk_s3::Client;
 use md5;
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom};
+use std::sync::{Arc, Mutex};
 use std::{path::Path, time::Duration};
 
 use colored::Colorize;
+use sha2::{Digest, Sha256};
+
+use crate::sse::ServerSideEncryption;
+use crate::storage_class;
+use crate::tagging;
+use crate::utils;
+
+// Object metadata with the size of the local file. Encrypted objects are bigger than the file they
+// came from, so the compare needs the original size to tell whether the file changed.
+pub const SOURCE_SIZE_METADATA: &str = "shuk-source-size";
 
 #[derive(Debug)]
 pub struct ObjectTags {
     pub managed_by: String,
     pub start_hash: String,
     pub end_hash: String,
+    // The `[tags]` of the configuration and `--tag`
+    pub extra: BTreeMap<String, String>,
 }
 
-// This converst the Struct into a list of tags the way the API accepts it
+// This converst the Struct into a list of tags the way the API accepts it, URL encoded
 // NOTE: Maybe I can create a dedicated function for this instead of using Dispay
 impl std::fmt::Display for ObjectTags {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "managed_by={}&start_hash={}&end_hash={}",
-            self.managed_by, self.start_hash, self.end_hash
-        )
+        write!(f, "{}", tagging::query_string(self.pairs()))
     }
 }
 
@@ -35,29 +46,147 @@ impl From<&ObjectTags> for String {
     }
 }
 
+impl ObjectTags {
+    pub fn new(start_hash: String, end_hash: String, shuk_config: &utils::Config) -> Self {
+        Self {
+            managed_by: "shuk".into(),
+            start_hash,
+            end_hash,
+            extra: shuk_config.tags.clone(),
+        }
+    }
+
+    fn pairs(&self) -> impl Iterator<Item = (&str, &str)> {
+        [
+            ("managed_by", self.managed_by.as_str()),
+            ("start_hash", self.start_hash.as_str()),
+            ("end_hash", self.end_hash.as_str()),
+        ]
+        .into_iter()
+        .chain(
+            self.extra
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str())),
+        )
+    }
+
+    // PutObjectTagging does not take the query string form, it wants a proper tag set
+    pub fn to_tagging(&self) -> Result<Tagging, anyhow::Error> {
+        let tag_set = self
+            .pairs()
+            .map(|(key, value)| Tag::builder().key(key).value(value).build())
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Tagging::builder().set_tag_set(Some(tag_set)).build()?)
+    }
+}
+
+// How much of the data is looked at to recognize its type
+pub const SNIFF_SIZE: usize = 8 * 1024; // 8KB
+
+// Works out the Content-Type from the file extension, or from the first bytes of the data when the
+// extension does not give it away. Anything unrecognized is served as a download.
+pub fn detect_content_type(name: &str, head: &[u8]) -> String {
+    if let Some(mime) = mime_guess::from_path(name).first() {
+        return mime.essence_str().to_string();
+    }
+    if let Some(kind) = infer::get(head) {
+        return kind.mime_type().to_string();
+    }
+    // Without a known signature, text is the most likely thing to be readable in a browser
+    if !head.is_empty() && !head.contains(&0) && utf8_prefix(head) {
+        return "text/plain; charset=utf-8".to_string();
+    }
+    "application/octet-stream".to_string()
+}
+
+// Whether `head` is UTF-8, allowing for a character that was cut in half at the end
+fn utf8_prefix(head: &[u8]) -> bool {
+    match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(error) => error.error_len().is_none() && head.len() - error.valid_up_to() < 4,
+    }
+}
+
+// The Content-Type for an object: whatever `--content-type` says, or else what it looks like
+pub fn content_type(shuk_config: &utils::Config, name: &str, head: &[u8]) -> String {
+    match &shuk_config.content_type {
+        Some(content_type) => content_type.clone(),
+        None => detect_content_type(name, head),
+    }
+}
+
+// The first bytes of a file, to recognize its type
+pub fn read_head(path: &Path) -> std::io::Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(SNIFF_SIZE);
+    File::open(path)?
+        .take(SNIFF_SIZE as u64)
+        .read_to_end(&mut head)?;
+    Ok(head)
+}
+
 pub async fn presign_file(
     client: &Client,
     bucket_name: &str,
     key: &str,
-    prefix: Option<String>,
     presigned_time: u64,
+    content_disposition: Option<String>,
+    sse: &ServerSideEncryption,
 ) -> Result<String, anyhow::Error> {
     log::trace!(
-        "Presigning file: {:?}/{} in bucket {} for duration of {}",
-        &prefix,
+        "Presigning file: {} in bucket {} for duration of {}",
         &key,
         &bucket_name,
         &presigned_time
     );
+    // A presigned URL for an archived object would only hand out an error
+    match get_file_metadata(client, bucket_name, key, sse).await {
+        Ok(Some(head)) => {
+            if let Some(reason) = storage_class::unavailable_reason(&head) {
+                return Err(anyhow::anyhow!(
+                    "Refusing to presign s3://{}/{}: {}. Restore it first, for example with `aws s3api restore-object`",
+                    bucket_name,
+                    key,
+                    reason
+                ));
+            }
+        }
+        Ok(None) => {}
+        Err(error) => log::warn!(
+            "Unable to check the storage class of s3://{}/{}: {}",
+            bucket_name,
+            key,
+            error
+        ),
+    }
+
     let expires_in = Duration::from_secs(presigned_time);
     log::trace!("Sending get_object request that will presing the file");
     let presigned_request = client
         .get_object()
         .bucket(bucket_name)
-        .key(format!("{}{}", prefix.unwrap_or("".into()), key))
+        .key(key)
+        .set_response_content_disposition(content_disposition)
+        .set_sse_customer_algorithm(sse.customer_algorithm())
+        .set_sse_customer_key(sse.customer_key())
+        .set_sse_customer_key_md5(sse.customer_key_md5())
         .presigned(PresigningConfig::expires_in(expires_in)?)
         .await?;
 
+    // The key is signed as a header, so it can not be part of the URL
+    if sse.customer_key().is_some() {
+        println!(
+            "{} | This object is encrypted with SSE-C. Downloading it needs the key, sent along with the URL in these headers:",
+            "NOTE".yellow()
+        );
+        for (name, value) in presigned_request.headers() {
+            if name == "x-amz-server-side-encryption-customer-key" {
+                println!("    {}: <the base64 encoded key>", name);
+            } else if name.starts_with("x-amz-server-side-encryption-customer-") {
+                println!("    {}: {}", name, value);
+            }
+        }
+    }
+
     Ok(presigned_request.uri().to_string())
 }
 
@@ -82,9 +211,11 @@ pub struct PartialFileHash {
     pub file_size: u64,
 }
 
+// How much of the start and the end of a file goes into the partial hash
+const SAMPLE_SIZE: usize = 8192;
+
 pub fn calculate_partial_hash(local_path: &Path) -> Result<PartialFileHash, anyhow::Error> {
     log::trace!("Calculating partial hash of {:?}", &local_path);
-    const SAMPLE_SIZE: usize = 8192;
 
     let mut file = File::open(local_path)?;
     let file_size = file.metadata()?.len();
@@ -120,6 +251,102 @@ pub fn calculate_partial_hash(local_path: &Path) -> Result<PartialFileHash, anyh
     })
 }
 
+// The SHA-256 of the whole file, as hex
+pub fn calculate_full_hash(local_path: &Path) -> Result<String, anyhow::Error> {
+    log::trace!("Calculating the SHA-256 of {:?}", &local_path);
+    let mut file = File::open(local_path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 1024 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+// Calculates the same partial hash as `calculate_partial_hash`, but for data that can only be
+// read once, like stdin. Feed it every chunk as it goes by and call `finish` at the end.
+#[derive(Debug, Default)]
+pub struct PartialHasher {
+    start: Vec<u8>,
+    tail: Vec<u8>,
+    file_size: u64,
+}
+
+impl PartialHasher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.file_size += data.len() as u64;
+
+        let missing = SAMPLE_SIZE - self.start.len();
+        self.start
+            .extend_from_slice(&data[..std::cmp::min(missing, data.len())]);
+
+        // Only the last SAMPLE_SIZE bytes we have seen are ever needed
+        self.tail
+            .extend_from_slice(&data[data.len().saturating_sub(SAMPLE_SIZE)..]);
+        if self.tail.len() > SAMPLE_SIZE {
+            self.tail.drain(..self.tail.len() - SAMPLE_SIZE);
+        }
+    }
+
+    pub fn file_size(&self) -> u64 {
+        self.file_size
+    }
+
+    pub fn finish(self) -> PartialFileHash {
+        let start_hash = format!("{:x}", md5::compute(&self.start));
+        let end_hash = if self.file_size > SAMPLE_SIZE as u64 {
+            format!("{:x}", md5::compute(&self.tail))
+        } else {
+            start_hash.clone()
+        };
+        PartialFileHash {
+            start_hash,
+            end_hash,
+            file_size: self.file_size,
+        }
+    }
+}
+
+// Feeds everything that is read through it into a `PartialHasher`. The hasher is shared, so the
+// hash of the original data can still be read after the reader was handed off to be encrypted or
+// uploaded.
+pub struct HashingReader<R> {
+    inner: R,
+    hasher: Arc<Mutex<PartialHasher>>,
+}
+
+impl<R: Read> HashingReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Arc::default(),
+        }
+    }
+
+    pub fn hasher(&self) -> Arc<Mutex<PartialHasher>> {
+        Arc::clone(&self.hasher)
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.hasher
+            .lock()
+            .map_err(|_| std::io::Error::other("the partial hasher is poisoned"))?
+            .update(&buf[..len]);
+        Ok(len)
+    }
+}
+
 fn is_not_found(error: &SdkError<HeadObjectError>) -> bool {
     error
         .as_service_error()
@@ -130,9 +357,10 @@ pub async fn file_exists_in_s3(
     client: &Client,
     bucket: &str,
     key: &str,
+    sse: &ServerSideEncryption,
 ) -> Result<bool, crate::s3_error::S3OperationError> {
     log::trace!("Testing if {:?} exists in bucket {:?}", key, bucket);
-    match client.head_object().bucket(bucket).key(key).send().await {
+    match head_object(client, bucket, key, sse).await {
         Ok(_) => {
             log::trace!("File {:?} has been found in bucket {:?}", key, bucket);
             Ok(true)
@@ -158,16 +386,32 @@ pub async fn file_exists_in_s3(
     }
 }
 
-async fn get_file_metadata(
+// SSE-C objects can only be looked at with their key
+async fn head_object(
     client: &Client,
     bucket: &str,
     key: &str,
-) -> Result<
-    Option<aws_sdk_s3::operation::head_object::HeadObjectOutput>,
-    crate::s3_error::S3OperationError,
-> {
+    sse: &ServerSideEncryption,
+) -> Result<HeadObjectOutput, SdkError<HeadObjectError>> {
+    client
+        .head_object()
+        .bucket(bucket)
+        .key(key)
+        .set_sse_customer_algorithm(sse.customer_algorithm())
+        .set_sse_customer_key(sse.customer_key())
+        .set_sse_customer_key_md5(sse.customer_key_md5())
+        .send()
+        .await
+}
+
+pub async fn get_file_metadata(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    sse: &ServerSideEncryption,
+) -> Result<Option<HeadObjectOutput>, crate::s3_error::S3OperationError> {
     log::trace!("Getting file metadata for {}:{}", bucket, key);
-    match client.head_object().bucket(bucket).key(key).send().await {
+    match head_object(client, bucket, key, sse).await {
         Ok(output) => {
             log::trace!(
                 "Metadata has been extracted for {:?} in bucket {:?}",
@@ -194,6 +438,23 @@ async fn get_file_metadata(
     }
 }
 
+// Whether the object at `key` is tagged with the same partial hashes as `tags`
+pub async fn has_tags(client: &Client, bucket: &str, key: &str, tags: &ObjectTags) -> bool {
+    let Ok(Some(output)) = get_file_tags(client, bucket, key).await else {
+        return false;
+    };
+    let tag = |name: &str| {
+        output
+            .tag_set()
+            .iter()
+            .find(|tag| tag.key() == name)
+            .map(|tag| tag.value().to_string())
+    };
+    tag("managed_by").as_ref() == Some(&tags.managed_by)
+        && tag("start_hash").as_ref() == Some(&tags.start_hash)
+        && tag("end_hash").as_ref() == Some(&tags.end_hash)
+}
+
 async fn get_file_tags(
     client: &Client,
     bucket: &str,
@@ -228,13 +489,26 @@ async fn get_file_tags(
     }
 }
 
+// What is at the key of a file, as far as shuk could tell before uploading it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Remote {
+    // Nothing
+    Missing,
+    // The same file, it only needs to be presigned
+    Identical,
+    // Another object, with the ETag it had when it was compared
+    Different(Option<String>),
+}
+
+// `object_metadata` is the HeadObject of the remote file, which the caller has looked up already
 pub async fn quick_compare(
     local_path: &Path,
     bucket_name: &str,
     key: &str,
     local_object_tags: &ObjectTags,
+    object_metadata: Option<&HeadObjectOutput>,
     c: &Client,
-) -> Result<bool, anyhow::Error> {
+) -> Result<Remote, anyhow::Error> {
     log::trace!(
         "Comparing local and remote files: {:?} and {}/{}",
         &local_path,
@@ -246,7 +520,6 @@ pub async fn quick_compare(
     let file_size = file.metadata()?.len();
     log::trace!("Local file {:?} size: {:?}", &local_path, &file_size);
 
-    let object_metadata = get_file_metadata(c, bucket_name, key).await?;
     log::trace!(
         "Remote file {}{} metadata: {:#?}",
         &bucket_name,
@@ -261,6 +534,15 @@ pub async fn quick_compare(
         &object_metadata
     );
 
+    let source_size = object_metadata
+        .and_then(|metadata| metadata.metadata())
+        .and_then(|metadata| metadata.get(SOURCE_SIZE_METADATA))
+        .and_then(|size| size.parse::<u64>().ok());
+    // An upload that replaces the object only goes through if it is still this one
+    let e_tag = object_metadata
+        .and_then(|metadata| metadata.e_tag())
+        .map(str::to_string);
+
     // NOTE: Very complex way of making sure the length of my remote file is extracted
     // if I cannot do it, I just return 0 and we reupload
     let s3_object_len = match object_metadata {
@@ -282,6 +564,8 @@ pub async fn quick_compare(
             },
         },
     };
+    // Objects that were changed on the way up keep the size of the local file in their metadata
+    let s3_object_len = source_size.unwrap_or(s3_object_len);
     log::trace!(
         "The size of the remote file {}{}: {}",
         &bucket_name,
@@ -326,11 +610,11 @@ pub async fn quick_compare(
         {
             //   If the same - presign
             log::trace!("Both file are the same: local_object_tags.start_hash = {} == remote_start_hash {}; local_object_tags.end_hash = {} == remote_end_hash = {} ", &local_object_tags.start_hash, &remote_start_hash, &local_object_tags.end_hash, &remote_end_hash);
-            Ok(true)
+            Ok(Remote::Identical)
         } else {
             log::trace!("The filenames are the same, but their partial hashes differ: local_object_tags.start_hash = {} != remote_start_hash {}; local_object_tags.end_hash = {} != remote_end_hash = {} ", &local_object_tags.start_hash, &remote_start_hash, &local_object_tags.end_hash, &remote_end_hash);
-            println!("{} | There seems to be a file with the same filename already at the destination. They are, also, the same sizes. HOWEVER, their partial hashes differ. I will assume that that they are different, so I will upload this one", "NOTE".yellow());
-            Ok(false)
+            println!("{} | There seems to be a file with the same filename already at the destination. They are, also, the same sizes. HOWEVER, their partial hashes differ. I will assume that that they are different", "NOTE".yellow());
+            Ok(Remote::Different(e_tag))
         }
     } else {
         log::trace!(
@@ -338,7 +622,71 @@ pub async fn quick_compare(
             &file_size,
             &s3_object_len
         );
-        println!("{} | There seems to be a file with the same filename already at the destination. They differ in sizes, I will assume that that they are different, so I will upload this one", "NOTE".yellow());
-        Ok(false)
+        println!("{} | There seems to be a file with the same filename already at the destination. They differ in sizes, I will assume that that they are different", "NOTE".yellow());
+        Ok(Remote::Different(e_tag))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streamed_hash_matches_file_hash() {
+        let dir = std::env::temp_dir();
+        for size in [0, 100, SAMPLE_SIZE, SAMPLE_SIZE + 1, 3 * SAMPLE_SIZE + 17] {
+            let data: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+            let path = dir.join(format!("shuk-hash-test-{}-{}", std::process::id(), size));
+            std::fs::write(&path, &data).unwrap();
+            let from_file = calculate_partial_hash(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            // Feed the data in uneven chunks to make sure chunk boundaries do not matter
+            let mut hasher = PartialHasher::new();
+            for chunk in data.chunks(3001) {
+                hasher.update(chunk);
+            }
+            let streamed = hasher.finish();
+
+            assert_eq!(streamed.file_size, from_file.file_size, "size {}", size);
+            assert_eq!(streamed.start_hash, from_file.start_hash, "size {}", size);
+            assert_eq!(streamed.end_hash, from_file.end_hash, "size {}", size);
+        }
+    }
+
+    #[test]
+    fn content_types_come_from_the_extension_then_the_data() {
+        assert_eq!(detect_content_type("report.pdf", b""), "application/pdf");
+        assert_eq!(detect_content_type("photo.JPG", b""), "image/jpeg");
+        // The extension wins, a docx is a zip file inside
+        assert_eq!(
+            detect_content_type("notes.docx", b"PK\x03\x04"),
+            "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+        );
+        assert_eq!(
+            detect_content_type("scan", b"%PDF-1.7\n%\xe2\xe3\xcf\xd3"),
+            "application/pdf"
+        );
+        assert_eq!(
+            detect_content_type("image", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+            "image/png"
+        );
+        assert_eq!(
+            detect_content_type("README", "Grüße\n".as_bytes()),
+            "text/plain; charset=utf-8"
+        );
+        // A multi-byte character cut off at the end of the sniffed bytes is still text
+        assert_eq!(
+            detect_content_type("notes", &"ü".as_bytes()[..1]),
+            "text/plain; charset=utf-8"
+        );
+        assert_eq!(
+            detect_content_type("blob", b"\0\x01\x02\xff"),
+            "application/octet-stream"
+        );
+        assert_eq!(
+            detect_content_type("empty", b""),
+            "application/octet-stream"
+        );
     }
 }
@@ -0,0 +1,291 @@
+// Resumable multipart uploads
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use dirs::home_dir;
+use serde::{Deserialize, Serialize};
+
+use crate::checksum::ChecksumAlgorithm;
+use crate::constants;
+
+// What we know about the local file when the upload starts. If any of this changes between runs,
+// the file is not the same one anymore and the journal can not be trusted.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FileIdentity {
+    pub file_size: u64,
+    pub modified_secs: u64,
+    pub modified_nanos: u32,
+    pub start_hash: String,
+    pub end_hash: String,
+}
+
+impl FileIdentity {
+    pub fn new(
+        metadata: &fs::Metadata,
+        start_hash: &str,
+        end_hash: &str,
+    ) -> Result<Self, anyhow::Error> {
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH)?;
+        Ok(Self {
+            file_size: metadata.len(),
+            modified_secs: modified.as_secs(),
+            modified_nanos: modified.subsec_nanos(),
+            start_hash: start_hash.to_string(),
+            end_hash: end_hash.to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct JournalPart {
+    pub part_number: i32,
+    pub e_tag: String,
+    // The checksum that was sent with the part, it is needed again to complete the upload
+    #[serde(default)]
+    pub checksum: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct UploadJournal {
+    pub bucket: String,
+    pub key: String,
+    pub upload_id: String,
+    pub part_size: u64,
+    pub local_path: PathBuf,
+    pub identity: FileIdentity,
+    #[serde(default)]
+    pub checksum_algorithm: Option<ChecksumAlgorithm>,
+    // The server-side encryption the upload was started with, see `ServerSideEncryption::fingerprint`
+    #[serde(default)]
+    pub encryption: Option<String>,
+    // A hash of the tags, headers, metadata, storage class and Object Lock settings the upload was
+    // created with. A journal without one is from before it was recorded and is never resumed.
+    #[serde(default)]
+    pub settings: Option<String>,
+    #[serde(default)]
+    pub parts: Vec<JournalPart>,
+    // Where this journal lives on disk, not part of the journal itself
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+pub fn journal_dir() -> Result<PathBuf, anyhow::Error> {
+    let home_dir = home_dir().ok_or_else(|| anyhow::anyhow!("Failed to get HOME directory"))?;
+    Ok(home_dir
+        .join(".config")
+        .join(constants::CONFIG_DIR_NAME)
+        .join(constants::JOURNAL_DIR_NAME))
+}
+
+// One journal per bucket, key and local file. The name is a hash so we do not have to worry about
+// slashes or odd characters in either the key or the path.
+fn journal_file_name(bucket: &str, key: &str, local_path: &Path) -> String {
+    let digest = md5::compute(format!("{}\n{}\n{}", bucket, key, local_path.display()));
+    format!("{:x}.toml", digest)
+}
+
+impl UploadJournal {
+    pub fn new(
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        part_size: u64,
+        local_path: &Path,
+        identity: FileIdentity,
+        checksum_algorithm: Option<ChecksumAlgorithm>,
+    ) -> Result<Self, anyhow::Error> {
+        let local_path = local_path.canonicalize()?;
+        let path = journal_dir()?.join(journal_file_name(bucket, key, &local_path));
+        Ok(Self {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            upload_id: upload_id.to_string(),
+            part_size,
+            local_path,
+            identity,
+            checksum_algorithm,
+            encryption: None,
+            settings: None,
+            parts: Vec::new(),
+            path,
+        })
+    }
+
+    // Looks for a journal left behind by an earlier run for the same file and key
+    pub fn load(bucket: &str, key: &str, local_path: &Path) -> Result<Option<Self>, anyhow::Error> {
+        let local_path = local_path.canonicalize()?;
+        let path = journal_dir()?.join(journal_file_name(bucket, key, &local_path));
+        Self::load_from(&path)
+    }
+
+    fn load_from(path: &Path) -> Result<Option<Self>, anyhow::Error> {
+        log::trace!("Looking for an upload journal at {:?}", &path);
+        if !path.try_exists()? {
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(path)?;
+        let mut journal: UploadJournal = toml::from_str(&contents).map_err(|error| {
+            anyhow::anyhow!("Upload journal {:?} is corrupted: {}", path, error)
+        })?;
+        journal.path = path.to_path_buf();
+        log::trace!("Upload journal found: {:#?}", &journal);
+        Ok(Some(journal))
+    }
+
+    // The journal is written to a temporary file first and then renamed over the old one, so a
+    // crash in the middle of saving never leaves a half written journal behind.
+    pub fn save(&self) -> Result<(), anyhow::Error> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temp_path = self.path.with_extension("toml.tmp");
+        fs::write(&temp_path, toml::to_string_pretty(self)?)?;
+        fs::rename(&temp_path, &self.path)?;
+        Ok(())
+    }
+
+    pub fn remove(&self) -> Result<(), anyhow::Error> {
+        log::trace!("Removing upload journal {:?}", &self.path);
+        match fs::remove_file(&self.path) {
+            Ok(()) => Ok(()),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // A journal can only be resumed if the local file is unchanged, the parts are cut,
+    // checksummed and encrypted the same way as they were the first time around, and the object
+    // would get the same settings
+    pub fn matches(
+        &self,
+        identity: &FileIdentity,
+        part_size: u64,
+        checksum_algorithm: Option<ChecksumAlgorithm>,
+        encryption: Option<&str>,
+        settings: Option<&str>,
+    ) -> bool {
+        self.identity == *identity
+            && self.part_size == part_size
+            && self.checksum_algorithm == checksum_algorithm
+            && self.encryption.as_deref() == encryption
+            && self.settings.as_deref() == settings
+    }
+
+    pub fn record_part(&mut self, part_number: i32, e_tag: &str, checksum: Option<&str>) {
+        self.parts.retain(|part| part.part_number != part_number);
+        self.parts.push(JournalPart {
+            part_number,
+            e_tag: e_tag.to_string(),
+            checksum: checksum.map(str::to_string),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SETTINGS: &str = "d41d8cd98f00b204e9800998ecf8427e";
+
+    fn identity() -> FileIdentity {
+        FileIdentity {
+            file_size: 5_000_000_000,
+            modified_secs: 1_700_000_000,
+            modified_nanos: 42,
+            start_hash: "aaaa".into(),
+            end_hash: "bbbb".into(),
+        }
+    }
+
+    fn journal_at(path: PathBuf) -> UploadJournal {
+        UploadJournal {
+            bucket: "example-bucket".into(),
+            key: "shuk/video.mp4".into(),
+            upload_id: "upload-123".into(),
+            part_size: 8 * 1024 * 1024,
+            local_path: PathBuf::from("/tmp/video.mp4"),
+            identity: identity(),
+            checksum_algorithm: None,
+            encryption: None,
+            settings: Some(SETTINGS.into()),
+            parts: Vec::new(),
+            path,
+        }
+    }
+
+    #[test]
+    fn journal_round_trips_through_disk() {
+        let dir = std::env::temp_dir().join(format!("shuk-journal-test-{}", std::process::id()));
+        let path = dir.join("journal.toml");
+        let mut journal = journal_at(path.clone());
+        journal.record_part(1, "\"etag-1\"", Some("yZRlqg=="));
+        journal.record_part(2, "\"etag-2\"", None);
+        journal.save().unwrap();
+
+        let loaded = UploadJournal::load_from(&path).unwrap().unwrap();
+        assert_eq!(loaded.upload_id, "upload-123");
+        assert_eq!(loaded.parts, journal.parts);
+        assert_eq!(loaded.path(), path.as_path());
+
+        loaded.remove().unwrap();
+        assert!(UploadJournal::load_from(&path).unwrap().is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn recording_a_part_twice_keeps_the_latest_etag() {
+        let mut journal = journal_at(PathBuf::new());
+        journal.record_part(3, "\"old\"", None);
+        journal.record_part(3, "\"new\"", None);
+        assert_eq!(
+            journal.parts,
+            vec![JournalPart {
+                part_number: 3,
+                e_tag: "\"new\"".into(),
+                checksum: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn changed_file_part_size_checksum_encryption_or_settings_does_not_match() {
+        let journal = journal_at(PathBuf::new());
+        let part_size = 8 * 1024 * 1024;
+        assert!(journal.matches(&identity(), part_size, None, None, Some(SETTINGS)));
+        assert!(!journal.matches(&identity(), 5 * 1024 * 1024, None, None, Some(SETTINGS)));
+        assert!(!journal.matches(
+            &identity(),
+            part_size,
+            Some(ChecksumAlgorithm::Crc32c),
+            None,
+            Some(SETTINGS)
+        ));
+        assert!(!journal.matches(
+            &identity(),
+            part_size,
+            None,
+            Some("aws:kms:alias/shuk:false"),
+            Some(SETTINGS)
+        ));
+        // New tags, metadata or headers, or a journal from before settings were recorded
+        assert!(!journal.matches(
+            &identity(),
+            part_size,
+            None,
+            None,
+            Some("9e107d9d372bb6826bd81d3542a419d6")
+        ));
+        let mut unrecorded = journal_at(PathBuf::new());
+        unrecorded.settings = None;
+        assert!(!unrecorded.matches(&identity(), part_size, None, None, Some(SETTINGS)));
+
+        let mut touched = identity();
+        touched.modified_secs += 1;
+        assert!(!journal.matches(&touched, part_size, None, None, Some(SETTINGS)));
+    }
+}
@@ -0,0 +1,295 @@
+// Object keys from a template
+use std::path::Path;
+use std::process::Command;
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local};
+use colored::Colorize;
+
+use crate::{file_management, utils};
+
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+// S3 keys can be at most 1024 bytes long
+const MAX_KEY_LENGTH: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Name,
+    Stem,
+    Ext,
+    Date(String),
+    Hostname,
+    User,
+    Uuid,
+    Hash8,
+}
+
+fn parse(template: &str) -> Result<Vec<Segment>, anyhow::Error> {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let mut placeholder = String::new();
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        closed = true;
+                        break;
+                    }
+                    placeholder.push(c);
+                }
+                if !closed {
+                    return Err(anyhow::anyhow!(
+                        "key_template {:?} has a `{{` without a `}}`, write `{{{{` for a literal one",
+                        template
+                    ));
+                }
+                if !literal.is_empty() {
+                    segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                }
+                segments.push(placeholder_segment(&placeholder)?);
+            }
+            '}' => {
+                return Err(anyhow::anyhow!(
+                    "key_template {:?} has a `}}` without a `{{`, write `}}}}` for a literal one",
+                    template
+                ))
+            }
+            c => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(literal));
+    }
+    Ok(segments)
+}
+
+fn placeholder_segment(placeholder: &str) -> Result<Segment, anyhow::Error> {
+    let segment = match placeholder.split_once(':') {
+        Some(("date", format)) => {
+            if StrftimeItems::new(format).any(|item| item == Item::Error) {
+                return Err(anyhow::anyhow!(
+                    "{{{}}} has an invalid date format",
+                    placeholder
+                ));
+            }
+            Segment::Date(format.to_string())
+        }
+        Some(_) => {
+            return Err(anyhow::anyhow!(
+                "Only {{date}} takes a format, not {{{}}}",
+                placeholder
+            ))
+        }
+        None => match placeholder {
+            "name" => Segment::Name,
+            "stem" => Segment::Stem,
+            "ext" => Segment::Ext,
+            "date" => Segment::Date(DEFAULT_DATE_FORMAT.to_string()),
+            "hostname" => Segment::Hostname,
+            "user" => Segment::User,
+            "uuid" => Segment::Uuid,
+            "hash8" => Segment::Hash8,
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Unknown placeholder {{{}}} in key_template",
+                    placeholder
+                ))
+            }
+        },
+    };
+    Ok(segment)
+}
+
+// Checks that the template parses, so a typo fails before the first upload
+pub fn check(shuk_config: &utils::Config) -> Result<(), anyhow::Error> {
+    let Some(template) = &shuk_config.key_template else {
+        return Ok(());
+    };
+    if !is_stable(&parse(template)?) {
+        println!(
+            "{} | key_template {:?} gives a file a new key on the next run, so it is uploaded again instead of only presigned, and an interrupted upload can not be resumed",
+            "NOTE".yellow(),
+            template
+        );
+    }
+    Ok(())
+}
+
+// Whether the next run gives a file the same key again, which finding it as already uploaded and
+// resuming its upload both rely on. `{uuid}` is new every time and `{date}` every day.
+pub fn is_reproducible(shuk_config: &utils::Config) -> bool {
+    match &shuk_config.key_template {
+        Some(template) => parse(template).is_ok_and(|segments| is_stable(&segments)),
+        None => true,
+    }
+}
+
+fn is_stable(segments: &[Segment]) -> bool {
+    !segments
+        .iter()
+        .any(|segment| matches!(segment, Segment::Uuid | Segment::Date(_)))
+}
+
+// The name an upload gets below `bucket_prefix`. `name` is what it would be called without a
+// template, and `file` the local file, if there is one. Streams have no file to hash.
+pub fn object_name(
+    shuk_config: &utils::Config,
+    name: &str,
+    file: Option<&Path>,
+) -> Result<String, anyhow::Error> {
+    let Some(template) = &shuk_config.key_template else {
+        return Ok(name.to_string());
+    };
+    let key = render(template, name, file, Local::now())?;
+    log::trace!("Rendered key_template {:?} for {}: {}", template, name, key);
+    if key.is_empty() || key.len() > MAX_KEY_LENGTH {
+        return Err(anyhow::anyhow!(
+            "key_template {:?} gives the key {:?} for {}, which S3 does not accept",
+            template,
+            key,
+            name
+        ));
+    }
+    Ok(key)
+}
+
+fn render(
+    template: &str,
+    name: &str,
+    file: Option<&Path>,
+    now: DateTime<Local>,
+) -> Result<String, anyhow::Error> {
+    let file_name = name.rsplit('/').next().unwrap_or(name);
+    let (stem, ext) = match file_name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, ext),
+        _ => (file_name, ""),
+    };
+
+    let mut key = String::new();
+    for segment in parse(template)? {
+        match segment {
+            Segment::Literal(literal) => key.push_str(&literal),
+            Segment::Name => key.push_str(name),
+            Segment::Stem => key.push_str(stem),
+            Segment::Ext => key.push_str(ext),
+            Segment::Date(format) => key.push_str(&now.format(&format).to_string()),
+            Segment::Hostname => key.push_str(&hostname()?),
+            Segment::User => key.push_str(&user()?),
+            Segment::Uuid => key.push_str(&uuid::Uuid::new_v4().to_string()),
+            Segment::Hash8 => {
+                let file = file.ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "{{hash8}} needs a file to hash, {} is uploaded as a stream",
+                        name
+                    )
+                })?;
+                key.push_str(&file_management::calculate_full_hash(file)?[..8]);
+            }
+        }
+    }
+    Ok(key)
+}
+
+fn hostname() -> Result<String, anyhow::Error> {
+    let from_command = Command::new("hostname")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok());
+    from_command
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .map(|hostname| hostname.trim().to_string())
+        .filter(|hostname| !hostname.is_empty())
+        .ok_or_else(|| anyhow::anyhow!("Unable to find the hostname for {{hostname}}"))
+}
+
+fn user() -> Result<String, anyhow::Error> {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .ok()
+        .filter(|user| !user.is_empty())
+        .ok_or_else(|| anyhow::anyhow!("Unable to find the user name for {{user}}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn now() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2026, 10, 17, 9, 30, 0).unwrap()
+    }
+
+    #[test]
+    fn placeholders_are_replaced() {
+        assert_eq!(
+            render(
+                "{date:%Y/%m/%d}/{stem}-final.{ext}",
+                "docs/report.pdf",
+                None,
+                now()
+            )
+            .unwrap(),
+            "2026/10/17/report-final.pdf"
+        );
+        assert_eq!(
+            render("{{{date}}}/{name}", "docs/report.pdf", None, now()).unwrap(),
+            "{2026-10-17}/docs/report.pdf"
+        );
+        assert_eq!(
+            render("{stem}.{ext}", ".bashrc", None, now()).unwrap(),
+            ".bashrc."
+        );
+        let key = render("{uuid}", "report.pdf", None, now()).unwrap();
+        assert_eq!(key.len(), 36);
+        assert_ne!(key, render("{uuid}", "report.pdf", None, now()).unwrap());
+    }
+
+    #[test]
+    fn hash8_needs_a_file() {
+        let path = std::env::temp_dir().join(format!("shuk-key-template-{}", std::process::id()));
+        std::fs::write(&path, b"hello").unwrap();
+        let key = render("{hash8}/{name}", "hello.txt", Some(&path), now());
+        std::fs::remove_file(&path).unwrap();
+        // The SHA-256 of "hello" starts with 2cf24dba
+        assert_eq!(key.unwrap(), "2cf24dba/hello.txt");
+
+        assert!(render("{hash8}", "stdin.txt", None, now()).is_err());
+    }
+
+    #[test]
+    fn uuids_and_dates_give_new_keys_on_the_next_run() {
+        let template =
+            |template: &str| utils::Config::from_toml(&format!("key_template = {:?}", template));
+        assert!(is_reproducible(&utils::Config::from_toml("")));
+        assert!(is_reproducible(&template("{user}/{hash8}-{name}")));
+        assert!(!is_reproducible(&template("{uuid}/{name}")));
+        assert!(!is_reproducible(&template("{date:%Y}/{name}")));
+    }
+
+    #[test]
+    fn invalid_templates_are_rejected() {
+        assert!(parse("{name}/{nope}").is_err());
+        assert!(parse("{date:%Q}").is_err());
+        assert!(parse("{uuid:4}").is_err());
+        assert!(parse("name}").is_err());
+        assert!(parse("{name").is_err());
+        assert_eq!(
+            parse("a{name}").unwrap(),
+            vec![Segment::Literal("a".into()), Segment::Name]
+        );
+    }
+}
@@ -1,5 +1,26 @@
+pub mod archive;
+pub mod checksum;
+pub mod cleanup;
+pub mod compression;
+pub mod conflict;
 pub mod constants;
+pub mod content_addressed;
+pub mod disposition;
+pub mod encryption;
 pub mod file_management;
+pub mod journal;
+pub mod key_template;
+pub mod object_lock;
+pub mod pipe;
+pub mod precondition;
+pub mod retry;
 pub mod s3_error;
+pub mod share;
+pub mod sse;
+pub mod storage_class;
+pub mod tagging;
+pub mod throttle;
+pub mod unguessable;
 pub mod upload;
+pub mod uploads;
 pub mod utils;
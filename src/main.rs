@@ -1,13 +1,35 @@
+pub mod archive;
+pub mod checksum;
+pub mod cleanup;
+pub mod compression;
+pub mod conflict;
 pub mod constants;
+pub mod content_addressed;
+pub mod disposition;
+pub mod encryption;
 pub mod file_management;
+pub mod journal;
+pub mod key_template;
+pub mod object_lock;
+pub mod pipe;
+pub mod precondition;
+pub mod retry;
 pub mod s3_error;
+pub mod share;
+pub mod sse;
+pub mod storage_class;
+pub mod tagging;
+pub mod throttle;
+pub mod unguessable;
 pub mod upload;
+pub mod uploads;
 pub mod utils;
 
 use clap::Parser;
+use std::collections::HashSet;
 use std::io;
 use std::io::Write;
-use upload::upload_object;
+use std::path::Path;
 use utils::check_for_config;
 use utils::initialize_config;
 use utils::print_warning;
@@ -57,17 +79,50 @@ async fn main() -> Result<(), anyhow::Error> {
         std::process::exit(0);
     }
 
+    if let Some(utils::Commands::Decrypt {
+        input,
+        output,
+        identity,
+    }) = &arguments.command
+    {
+        // Decrypting does not need a bucket, so the configuration is only read if there is one
+        let identity = match identity {
+            Some(identity) => Some(identity.clone()),
+            None if check_for_config() => utils::Config::load_config()?.identity_file,
+            None => None,
+        };
+        if let Err(error) = encryption::decrypt_file(input, output.as_deref(), identity.as_deref())
+        {
+            eprintln!("Error: {}", error);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     // parse configuration
-    let shuk_config = match utils::Config::load_config() {
+    let mut shuk_config = match utils::Config::load_config() {
         Ok(config) => {
             log::trace!("The configuration is loaded from the file: {:#?}", &config);
             config
-        },
+        }
         Err(e) => {
             eprintln!("Failed to load configuration. Make sure that your config file is located at ~/.config/shuk: {}", e);
             std::process::exit(1);
         }
     };
+    shuk_config.apply_args(&arguments);
+    let checked = sse::prepare(&mut shuk_config)
+        .and_then(|()| tagging::check(&shuk_config))
+        .and_then(|()| object_lock::check(&shuk_config))
+        .and_then(|()| key_template::check(&shuk_config))
+        .and_then(|()| unguessable::check(&shuk_config))
+        .and_then(|()| content_addressed::check(&shuk_config));
+    if let Err(error) = checked {
+        eprintln!("Error: {}", error);
+        std::process::exit(1);
+    }
+    // From here on multipart uploads may be running, and Ctrl-C should not leave them behind
+    cleanup::handle_signals();
     // Configure AWS and create the initial S3 client.
     let config = utils::configure_aws(
         shuk_config
@@ -76,154 +131,225 @@ async fn main() -> Result<(), anyhow::Error> {
             .unwrap_or("us-east-1")
             .to_string(),
         shuk_config.aws_profile.as_ref(),
+        retry::sdk_retry_config(&shuk_config),
     )
     .await;
     let mut s3_client = aws_sdk_s3::Client::new(&config);
 
-    let key = arguments.filename.clone();
-    let file_name = arguments
-        .filename
-        .expect("Unable to determine the file name from the command line parameters");
-    // NOTE: Getting just the key (file name)
-    let key_file_name = key
-        .as_ref()
-        .and_then(|path| path.file_name())
-        .and_then(|name| name.to_str())
-        .map(|s| s.trim_matches('"'))
-        .ok_or_else(|| anyhow::anyhow!("Invalid filename provided"))?;
-
-    let key_full = if shuk_config.bucket_prefix.is_some() {
-        format!(
-            "{}{}",
-            &shuk_config
-                .bucket_prefix
-                .clone()
-                .unwrap_or_else(|| "".into()),
-            &key_file_name
+    if let Some(utils::Commands::Uploads {
+        keys,
+        older_than,
+        abort,
+        yes,
+    }) = &arguments.command
+    {
+        let selection = uploads::Selection {
+            keys: keys.clone(),
+            older_than: *older_than,
+        };
+        if let Err(error) = uploads::manage_uploads(
+            &mut s3_client,
+            &config,
+            &shuk_config,
+            &selection,
+            *abort,
+            *yes,
         )
-    } else {
-        key_file_name.to_string()
-    };
-
-    // Calculate partial MD5 of the local file
-    let md5_of_file = file_management::calculate_partial_hash(&file_name.clone())?;
-    // Prep the tags
-    let file_tags = file_management::ObjectTags {
-        managed_by: "shuk".into(),
-        start_hash: md5_of_file.start_hash,
-        end_hash: md5_of_file.end_hash,
-    };
-    log::trace!("File tags defined: {:#?}", &file_tags);
-
-    let fail_file_check = |error: &crate::s3_error::S3OperationError| {
-        eprintln!(
-            "Error: Could not determine whether s3://{}/{} exists.",
-            shuk_config.bucket_name, key_full
-        );
-        eprintln!("Details: {error}");
-        eprintln!(
-            "Refusing to upload because Shuk could not safely determine whether it would replace an existing object."
-        );
-        if let Some(request_id) = error.extended_request_id() {
-            log::debug!("S3 extended request ID: {request_id}");
+        .await
+        {
+            eprintln!("Error: {}", error);
+            std::process::exit(1);
         }
+        return Ok(());
+    }
+
+    if let Err(error) = arguments.check_sources() {
+        eprintln!("Error: {}", error);
         std::process::exit(1);
-    };
+    }
+    if let Err(error) = object_lock::check_bucket(&mut s3_client, &config, &shuk_config).await {
+        eprintln!("Error: {}", error);
+        std::process::exit(1);
+    }
 
-    let object_exists = match file_management::file_exists_in_s3(
-        &s3_client,
-        &shuk_config.bucket_name,
-        key_full.as_str(),
-    )
-    .await
-    {
-        Ok(exists) => exists,
-        Err(error) => {
-            let Some(bucket_region) = error.retry_region().map(str::to_string) else {
-                fail_file_check(&error)
-            };
-            let configured_region = error.configured_region().unwrap_or("unknown");
-            eprintln!(
-                "Warning: AWS selected region `{configured_region}`, but bucket `{}` is in `{bucket_region}`.",
-                shuk_config.bucket_name
-            );
-            eprintln!("Retrying automatically in `{bucket_region}`.");
-            eprintln!(
-                "Tip: Update your AWS region setting or set fallback_region = \"{bucket_region}\" in the Shuk configuration to avoid this extra request."
-            );
-
-            s3_client = utils::s3_client_for_region(&config, bucket_region);
-            match file_management::file_exists_in_s3(
-                &s3_client,
-                &shuk_config.bucket_name,
-                key_full.as_str(),
+    let mut results = Vec::new();
+    if let Some(key_file_name) = arguments.stdin_object_name() {
+        let result = match unguessable::object_name(&shuk_config, key_file_name) {
+            Ok(key_name) => share::share_stdin(
+                &mut s3_client,
+                &config,
+                key_file_name,
+                &key_name,
+                arguments.upload_only,
+                &shuk_config,
             )
             .await
+            .unwrap_or_else(|error| {
+                eprintln!("Error uploading stdin: {}", error);
+                share::ShareResult::failed(&share::object_key(&shuk_config, &key_name), &error)
+            }),
+            Err(error) => {
+                eprintln!("Error: {}", error);
+                share::ShareResult::failed(key_file_name, &error)
+            }
+        };
+        results.push(result);
+    } else if let Some(format) = arguments.archive {
+        let mut seen_keys = HashSet::new();
+        for dir in &arguments.filenames {
+            let key_name = match archive::archive_name(dir, format)
+                .and_then(|name| unguessable::object_name(&shuk_config, &name))
             {
-                Ok(exists) => exists,
-                Err(retry_error) => fail_file_check(&retry_error),
+                Ok(key_name) => key_name,
+                Err(error) => {
+                    eprintln!("Error: {}", error);
+                    results.push(share::ShareResult::failed(&dir.to_string_lossy(), &error));
+                    continue;
+                }
+            };
+            let key_full = share::object_key(&shuk_config, &key_name);
+            if let Err(result) = check_unique_key(&mut seen_keys, &key_full, dir) {
+                results.push(result);
+                continue;
             }
-        }
-    };
 
-    let just_upload = if object_exists {
-        file_management::quick_compare(
-            &file_name,
-            &shuk_config.bucket_name,
-            key_full.as_str(),
-            &file_tags,
-            &s3_client,
-        )
-        .await
-        .map_err(|error| {
-            anyhow::anyhow!(
-                "Could not compare the local file with s3://{}/{}: {}",
-                shuk_config.bucket_name,
-                key_full,
-                error
+            let result = share::share_archive(
+                &mut s3_client,
+                &config,
+                dir,
+                &key_name,
+                format,
+                arguments.upload_only,
+                &shuk_config,
             )
-        })?
+            .await
+            .unwrap_or_else(|error| {
+                eprintln!("Error uploading an archive of {:?}: {}", dir, error);
+                share::ShareResult::failed(&key_full, &error)
+            });
+            results.push(result);
+        }
     } else {
-        false
-    };
+        let (sources, failures) =
+            share::collect_sources(&arguments.filenames, arguments.recursive, &shuk_config);
+        results.extend(failures);
 
-    // Upload-only early exit: file already exists and matches
-    if arguments.upload_only && just_upload {
-        log::trace!("Upload-only mode: file already exists in S3 and matches, no action needed.");
+        let mut seen_keys = HashSet::new();
+        for source in &sources {
+            let named = unguessable::file_key(&shuk_config, &source.path).and_then(|key_record| {
+                let key_name = match &key_record {
+                    Some(key_record) => key_record.key_name.clone(),
+                    None => content_addressed::object_name(
+                        &shuk_config,
+                        &source.key_name,
+                        &source.path,
+                    )?,
+                };
+                Ok((key_name, key_record))
+            });
+            let (key_name, mut key_record) = match named {
+                Ok(named) => named,
+                Err(error) => {
+                    eprintln!("Error: {}", error);
+                    results.push(share::ShareResult::failed(&source.key_name, &error));
+                    continue;
+                }
+            };
+            let key_full = share::object_key(&shuk_config, &key_name);
+            // Files with the same contents share their object, the second one is only presigned
+            if !content_addressed::is_enabled(&shuk_config) {
+                if let Err(result) = check_unique_key(&mut seen_keys, &key_full, &source.path) {
+                    results.push(result);
+                    continue;
+                }
+            }
+
+            // Recorded before the upload, so the next run gets the same key and can resume an
+            // upload that is interrupted
+            if let Some(key_record) = &key_record {
+                save_key_record(key_record, &source.path);
+            }
+
+            let result = share::share_file(
+                &mut s3_client,
+                &config,
+                &source.path,
+                &key_name,
+                arguments.upload_only,
+                &shuk_config,
+            )
+            .await
+            .unwrap_or_else(|error| {
+                eprintln!("Error uploading file {:?}: {}", source.path, error);
+                share::ShareResult::failed(&key_full, &error)
+            });
+            // A conflict can put the file under a new name, which is where the next run finds it
+            if let Some(key_record) = key_record
+                .as_mut()
+                .filter(|_| result.action == share::Action::Renamed)
+            {
+                key_record.move_to(&shuk_config, &result.key);
+                save_key_record(key_record, &source.path);
+            }
+            results.push(result);
+        }
+    }
+
+    report_results(&results, &shuk_config);
+
+    Ok(())
+}
+
+// Without the record the next run picks a new random key, which is only worth a warning
+fn save_key_record(key_record: &unguessable::KeyRecord, path: &Path) {
+    if let Err(error) = key_record.save() {
+        eprintln!("Warning: Unable to record the key of {:?}: {}", path, error);
+    }
+}
+
+// Two files with the same name would end up at the same key and overwrite each other
+fn check_unique_key(
+    seen_keys: &mut HashSet<String>,
+    key_full: &str,
+    path: &Path,
+) -> Result<(), share::ShareResult> {
+    if seen_keys.insert(key_full.to_string()) {
+        return Ok(());
+    }
+    let error = anyhow::anyhow!("{:?} has the same object key as an earlier file", path);
+    eprintln!("Error: {}", error);
+    Err(share::ShareResult::failed(key_full, &error))
+}
+
+// Prints the summary table when more than one file was shared, copies every presigned URL to the
+// clipboard if needed, and exits with an error if any of the files failed or had a conflict
+fn report_results(results: &[share::ShareResult], shuk_config: &utils::Config) {
+    if results.len() > 1 {
         println!("========================================");
-        println!("✅ | File already exists in S3: {}", key_file_name);
-        println!("✅ | No action taken (upload-only mode)");
+        print!("{}", share::format_summary(results));
         println!("========================================");
-        std::process::exit(0);
+        println!("{}", share::format_counts(results));
     }
 
-    match upload_object(
-        &s3_client,
-        &file_name,
-        key_file_name,
-        file_tags,
-        just_upload,
-        arguments.upload_only,
-        &shuk_config,
-    )
-    .await
-    {
-        Ok(Some(presigned_url)) => {
-            if shuk_config.use_clipboard.unwrap_or(false) {
-                if let Err(e) = utils::set_into_clipboard(presigned_url) {
-                    eprintln!("Error setting clipboard: {}", e);
-                }
-            }
-        }
-        Ok(None) => {
-            // Upload-only mode succeeded — no presigned URL to handle
-            log::trace!("Upload-only mode: presigned URL generation was skipped, clipboard operations skipped.");
-        }
-        Err(e) => {
-            eprintln!("Error uploading file: {}", e);
-            std::process::exit(1);
+    let urls: Vec<&str> = results
+        .iter()
+        .filter_map(|result| result.url.as_deref())
+        .collect();
+    if urls.is_empty() {
+        // Upload-only mode succeeded — no presigned URL to handle
+        log::trace!("No presigned URLs were generated, clipboard operations skipped.");
+    } else if shuk_config.use_clipboard.unwrap_or(false) {
+        if let Err(e) = utils::set_into_clipboard(urls.join("\n")) {
+            eprintln!("Error setting clipboard: {}", e);
         }
     }
 
-    Ok(())
+    if results.is_empty() {
+        eprintln!("Error: There were no files to upload");
+        std::process::exit(1);
+    }
+    let status = share::exit_status(results, shuk_config);
+    if status != 0 {
+        std::process::exit(status);
+    }
 }
@@ -0,0 +1,275 @@
+// Object Lock retention and legal hold for uploaded objects
+use std::time::Duration;
+
+use aws_sdk_s3::operation::head_object::HeadObjectOutput;
+use aws_sdk_s3::types::{self, ObjectLockEnabled, ObjectLockLegalHoldStatus};
+use aws_sdk_s3::Client;
+use aws_smithy_types::date_time::Format;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::s3_error::S3OperationError;
+use crate::{share, utils};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum LockMode {
+    #[value(name = "GOVERNANCE")]
+    Governance,
+    #[value(name = "COMPLIANCE")]
+    Compliance,
+}
+
+impl LockMode {
+    pub fn sdk_mode(&self) -> types::ObjectLockMode {
+        match self {
+            LockMode::Governance => types::ObjectLockMode::Governance,
+            LockMode::Compliance => types::ObjectLockMode::Compliance,
+        }
+    }
+}
+
+// When the retention ends, either a fixed point in time or a while after the upload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetainUntil {
+    Date(DateTime<Utc>),
+    After(Duration),
+}
+
+impl RetainUntil {
+    pub fn resolve(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            RetainUntil::Date(date) => *date,
+            RetainUntil::After(duration) => {
+                now + chrono::Duration::from_std(*duration).unwrap_or(chrono::Duration::MAX)
+            }
+        }
+    }
+}
+
+// A date like "2027-03-31" (midnight UTC), an RFC 3339 timestamp like "2027-03-31T12:00:00Z", or an
+// age like "90d" that is counted from the upload
+pub fn parse_retain_until(value: &str) -> Result<RetainUntil, String> {
+    let value = value.trim();
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(RetainUntil::Date(
+            date.and_time(Default::default()).and_utc(),
+        ));
+    }
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Ok(RetainUntil::Date(date.with_timezone(&Utc)));
+    }
+    utils::parse_age(value)
+        .map(RetainUntil::After)
+        .map_err(|_| {
+            format!(
+                "`{}` is neither a date like 2027-03-31, a timestamp like 2027-03-31T12:00:00Z, nor an age like 90d",
+                value
+            )
+        })
+}
+
+// For `--retain-until`, which is stored in the configuration as it was written
+pub fn parse_retain_until_arg(value: &str) -> Result<String, String> {
+    parse_retain_until(value).map(|_| value.trim().to_string())
+}
+
+pub fn is_requested(shuk_config: &utils::Config) -> bool {
+    shuk_config.object_lock_mode.is_some()
+        || shuk_config.object_lock_retain_until.is_some()
+        || shuk_config.object_lock_legal_hold == Some(true)
+}
+
+// Checks that the Object Lock settings go together
+pub fn check(shuk_config: &utils::Config) -> Result<(), anyhow::Error> {
+    match (
+        shuk_config.object_lock_mode,
+        &shuk_config.object_lock_retain_until,
+    ) {
+        (Some(_), Some(retain_until)) => {
+            let retain_until = parse_retain_until(retain_until).map_err(anyhow::Error::msg)?;
+            let now = Utc::now();
+            if retain_until.resolve(now) <= now {
+                return Err(anyhow::anyhow!(
+                    "object_lock_retain_until {} is in the past",
+                    retain_until.resolve(now).to_rfc3339()
+                ));
+            }
+            Ok(())
+        }
+        (None, None) => Ok(()),
+        _ => Err(anyhow::anyhow!(
+            "object_lock_mode and object_lock_retain_until only work together, S3 needs both for a retention period"
+        )),
+    }
+}
+
+// The Object Lock headers for a new object. Whatever is not in use stays `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ObjectLock {
+    pub mode: Option<types::ObjectLockMode>,
+    pub retain_until: Option<aws_smithy_types::DateTime>,
+    pub legal_hold: Option<ObjectLockLegalHoldStatus>,
+}
+
+// The headers for the configuration, after `check` has looked at it. A retention given as an age
+// starts now.
+pub fn settings(shuk_config: &utils::Config) -> ObjectLock {
+    let retain_until = shuk_config
+        .object_lock_retain_until
+        .as_deref()
+        .and_then(|value| parse_retain_until(value).ok())
+        .map(|retain_until| {
+            aws_smithy_types::DateTime::from_secs(retain_until.resolve(Utc::now()).timestamp())
+        });
+    ObjectLock {
+        mode: shuk_config.object_lock_mode.map(|mode| mode.sdk_mode()),
+        retain_until,
+        legal_hold: shuk_config
+            .object_lock_legal_hold
+            .filter(|legal_hold| *legal_hold)
+            .map(|_| ObjectLockLegalHoldStatus::On),
+    }
+}
+
+// Makes sure the bucket can lock objects before anything is uploaded to it. Without Object Lock
+// S3 refuses every upload that asks for it, which would only show up halfway through.
+pub async fn check_bucket(
+    client: &mut Client,
+    sdk_config: &aws_config::SdkConfig,
+    shuk_config: &utils::Config,
+) -> Result<(), anyhow::Error> {
+    if !is_requested(shuk_config) {
+        return Ok(());
+    }
+    let bucket = &shuk_config.bucket_name;
+    let enabled = match lock_enabled(client, bucket).await {
+        Ok(enabled) => enabled,
+        Err(error) => {
+            let Some(bucket_region) = error.retry_region().map(str::to_string) else {
+                return Err(error.into());
+            };
+            share::switch_region(client, sdk_config, bucket, &error, bucket_region);
+            lock_enabled(client, bucket).await?
+        }
+    };
+    if !enabled {
+        return Err(anyhow::anyhow!(
+            "Bucket {} does not have Object Lock enabled, so its objects can not get a retention period or a legal hold. Enable Object Lock on the bucket, or remove the object_lock settings.",
+            bucket
+        ));
+    }
+    log::trace!("Object Lock is enabled on bucket {}", bucket);
+    Ok(())
+}
+
+async fn lock_enabled(client: &Client, bucket: &str) -> Result<bool, S3OperationError> {
+    log::trace!("Getting the Object Lock configuration of bucket {}", bucket);
+    match client
+        .get_object_lock_configuration()
+        .bucket(bucket)
+        .send()
+        .await
+    {
+        Ok(output) => Ok(output
+            .object_lock_configuration()
+            .and_then(|configuration| configuration.object_lock_enabled())
+            == Some(&ObjectLockEnabled::Enabled)),
+        Err(error) => {
+            let error = S3OperationError::from_sdk_error(
+                "GetObjectLockConfiguration",
+                client,
+                bucket,
+                None,
+                &error,
+            );
+            if error.code() == Some("ObjectLockConfigurationNotFoundError") {
+                Ok(false)
+            } else {
+                Err(error)
+            }
+        }
+    }
+}
+
+// The retention and legal hold of an object that is already in the bucket, if it has any. S3 only
+// reports them to callers that may read them.
+pub fn describe(head: &HeadObjectOutput) -> Option<String> {
+    let mut locks = Vec::new();
+    if let Some(mode) = head.object_lock_mode() {
+        let until = head
+            .object_lock_retain_until_date()
+            .and_then(|date| date.fmt(Format::DateTime).ok())
+            .unwrap_or_else(|| "an unknown date".to_string());
+        locks.push(format!("{} retention until {}", mode.as_str(), until));
+    }
+    if head.object_lock_legal_hold_status() == Some(&ObjectLockLegalHoldStatus::On) {
+        locks.push("legal hold".to_string());
+    }
+    if locks.is_empty() {
+        None
+    } else {
+        Some(locks.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retain_until_is_a_date_a_timestamp_or_an_age() {
+        let midnight = DateTime::parse_from_rfc3339("2027-03-31T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            parse_retain_until("2027-03-31"),
+            Ok(RetainUntil::Date(midnight))
+        );
+        assert_eq!(
+            parse_retain_until("2027-03-31T02:00:00+02:00"),
+            Ok(RetainUntil::Date(midnight))
+        );
+        let after = parse_retain_until("90d").unwrap();
+        assert_eq!(
+            after.resolve(midnight),
+            midnight + chrono::Duration::days(90)
+        );
+        assert!(parse_retain_until("next year").is_err());
+    }
+
+    #[test]
+    fn retention_needs_a_mode_and_a_future_date() {
+        check(&utils::Config::from_toml(
+            "object_lock_mode = \"GOVERNANCE\"\nobject_lock_retain_until = \"30d\"",
+        ))
+        .unwrap();
+        check(&utils::Config::from_toml("object_lock_legal_hold = true")).unwrap();
+        assert!(check(&utils::Config::from_toml(
+            "object_lock_mode = \"COMPLIANCE\""
+        ))
+        .is_err());
+        assert!(check(&utils::Config::from_toml(
+            "object_lock_retain_until = \"30d\""
+        ))
+        .is_err());
+        assert!(check(&utils::Config::from_toml(
+            "object_lock_mode = \"COMPLIANCE\"\nobject_lock_retain_until = \"2020-01-01\""
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn existing_locks_are_described() {
+        assert_eq!(describe(&HeadObjectOutput::builder().build()), None);
+        let head = HeadObjectOutput::builder()
+            .object_lock_mode(types::ObjectLockMode::Compliance)
+            .object_lock_retain_until_date(aws_smithy_types::DateTime::from_secs(1_806_451_200))
+            .object_lock_legal_hold_status(ObjectLockLegalHoldStatus::On)
+            .build();
+        assert_eq!(
+            describe(&head).as_deref(),
+            Some("COMPLIANCE retention until 2027-03-31T00:00:00Z, legal hold")
+        );
+    }
+}
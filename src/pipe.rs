@@ -0,0 +1,138 @@
+// Turns a writer running on its own thread into something the uploader can read from
+use std::io::{self, BufWriter, Read, Write};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::thread;
+
+// How much output is buffered before it is handed to the reader, and how many of those buffers
+// can wait in the channel. The uploader reads whole parts anyway, so this only has to be big
+// enough to keep the writer from stalling on every small write.
+const CHUNK_SIZE: usize = 1024 * 1024; // 1MB
+const CHANNEL_CHUNKS: usize = 8;
+
+// Messages from the writer thread. The writer always ends with `Done` or `Failed`, so a channel
+// that closes without either means the thread died and the output is incomplete.
+enum Message {
+    Data(Vec<u8>),
+    Done,
+    Failed(io::Error),
+}
+
+pub struct ChannelWriter {
+    sender: SyncSender<Message>,
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.sender
+            .send(Message::Data(buf.to_vec()))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the upload has stopped"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// The reading end of the pipe. Dropping it stops the writer thread on its next write.
+pub struct PipeReader {
+    receiver: Receiver<Message>,
+    chunk: Vec<u8>,
+    position: usize,
+    finished: bool,
+}
+
+impl Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.chunk.len() {
+            if self.finished {
+                return Ok(0);
+            }
+            match self.receiver.recv() {
+                Ok(Message::Data(chunk)) => {
+                    self.chunk = chunk;
+                    self.position = 0;
+                }
+                Ok(Message::Done) => self.finished = true,
+                Ok(Message::Failed(error)) => return Err(error),
+                Err(_) => {
+                    return Err(io::Error::other(
+                        "the writer stopped before all of the data was written",
+                    ))
+                }
+            }
+        }
+        let len = std::cmp::min(buf.len(), self.chunk.len() - self.position);
+        buf[..len].copy_from_slice(&self.chunk[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+// Runs `write` on a new thread and returns everything it writes as a stream. An error from
+// `write` comes out of the reader as a read error, so the upload fails instead of sharing
+// truncated data.
+pub fn spawn_writer<F>(name: &str, write: F) -> Result<PipeReader, anyhow::Error>
+where
+    F: FnOnce(&mut BufWriter<ChannelWriter>) -> io::Result<()> + Send + 'static,
+{
+    let (sender, receiver) = sync_channel(CHANNEL_CHUNKS);
+    let thread_name = name.to_string();
+
+    thread::Builder::new()
+        .name(format!("shuk-{}", name))
+        .spawn(move || {
+            let mut writer = BufWriter::with_capacity(
+                CHUNK_SIZE,
+                ChannelWriter {
+                    sender: sender.clone(),
+                },
+            );
+            let result = write(&mut writer).and_then(|()| writer.flush());
+            log::trace!("The {} writer finished: {:?}", &thread_name, &result);
+            // If the reader is gone there is nobody left to tell
+            let _ = sender.send(match result {
+                Ok(()) => Message::Done,
+                Err(error) => Message::Failed(error),
+            });
+        })?;
+
+    Ok(PipeReader {
+        receiver,
+        chunk: Vec::new(),
+        position: 0,
+        finished: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reader_gets_everything_that_was_written() {
+        let data: Vec<u8> = (0..3 * CHUNK_SIZE + 17).map(|i| (i % 251) as u8).collect();
+        let expected = data.clone();
+        let mut reader = spawn_writer("test", move |writer| writer.write_all(&data)).unwrap();
+
+        let mut received = Vec::new();
+        reader.read_to_end(&mut received).unwrap();
+        assert_eq!(received, expected);
+    }
+
+    #[test]
+    fn writer_errors_become_read_errors() {
+        let mut reader = spawn_writer("test", |writer| {
+            writer.write_all(b"partial")?;
+            Err(io::Error::other("disk on fire"))
+        })
+        .unwrap();
+
+        let mut received = Vec::new();
+        let error = reader.read_to_end(&mut received).unwrap_err();
+        assert_eq!(error.to_string(), "disk on fire");
+    }
+}
@@ -0,0 +1,212 @@
+// Conditional writes
+use std::fmt;
+use std::future::Future;
+
+use crate::file_management::Remote;
+use crate::s3_error::S3OperationError;
+
+// The error codes of a write whose condition did not hold. `ConditionalRequestConflict` is what S3
+// answers when another conditional write to the same key finished first.
+const PRECONDITION_ERROR_CODES: &[&str] = &["PreconditionFailed", "ConditionalRequestConflict"];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum WriteCondition {
+    // Whatever is at the key is replaced
+    #[default]
+    Overwrite,
+    // `If-None-Match: *`, nothing may be at the key yet
+    Create,
+    // `If-Match`, the object at the key must still have this ETag
+    Replace(String),
+}
+
+impl WriteCondition {
+    // What a write may expect after the key was looked at. Without an ETag there is nothing to
+    // hold a different object to.
+    pub fn for_remote(remote: &Remote) -> Self {
+        match remote {
+            Remote::Missing => WriteCondition::Create,
+            Remote::Different(Some(e_tag)) => WriteCondition::Replace(e_tag.clone()),
+            Remote::Different(None) | Remote::Identical => WriteCondition::Overwrite,
+        }
+    }
+
+    pub fn if_none_match(&self) -> Option<String> {
+        match self {
+            WriteCondition::Create => Some("*".to_string()),
+            _ => None,
+        }
+    }
+
+    pub fn if_match(&self) -> Option<String> {
+        match self {
+            WriteCondition::Replace(e_tag) => Some(e_tag.clone()),
+            _ => None,
+        }
+    }
+}
+
+// The object at the key changed while it was being uploaded to
+#[derive(Debug)]
+pub struct PreconditionFailed {
+    bucket: String,
+    key: String,
+    condition: WriteCondition,
+    source: S3OperationError,
+}
+
+impl fmt::Display for PreconditionFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.condition {
+            WriteCondition::Replace(e_tag) => write!(
+                f,
+                "s3://{}/{} was replaced by someone else while shuk was uploading (it no longer has the ETag {}), so it was not overwritten.",
+                self.bucket, self.key, e_tag
+            )?,
+            _ => write!(
+                f,
+                "s3://{}/{} was created by someone else while shuk was uploading, so it was not overwritten.",
+                self.bucket, self.key
+            )?,
+        }
+        write!(
+            f,
+            " Run shuk again to compare the file with the object that is there now, or share it under another key, for example with --key."
+        )
+    }
+}
+
+impl std::error::Error for PreconditionFailed {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+// Whether an upload failed because the object at its key changed. Trying again fails the same way.
+pub fn is_failed(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<PreconditionFailed>().is_some()
+}
+
+fn is_precondition_failure(code: Option<&str>) -> bool {
+    code.is_some_and(|code| PRECONDITION_ERROR_CODES.contains(&code))
+}
+
+// Turns the error of a conditional write into `PreconditionFailed` if its condition did not hold
+pub fn write_error(
+    error: S3OperationError,
+    bucket: &str,
+    key: &str,
+    condition: &WriteCondition,
+) -> anyhow::Error {
+    if *condition == WriteCondition::Overwrite || !is_precondition_failure(error.code()) {
+        return error.into();
+    }
+    log::trace!(
+        "The write to {}/{} failed its condition {:?}",
+        bucket,
+        key,
+        condition
+    );
+    PreconditionFailed {
+        bucket: bucket.to_string(),
+        key: key.to_string(),
+        condition: condition.clone(),
+        source: error,
+    }
+    .into()
+}
+
+// The SDK retries a write whose response got lost. If the lost attempt went through, the retry
+// finds shuk's own object and fails its condition. `is_ours` looks at the object at the key, and
+// the write only failed if it is someone else's.
+pub async fn check_write_error(
+    error: S3OperationError,
+    bucket: &str,
+    key: &str,
+    condition: &WriteCondition,
+    is_ours: impl Future<Output = bool>,
+) -> Result<(), anyhow::Error> {
+    let error = write_error(error, bucket, key, condition);
+    if is_failed(&error) && is_ours.await {
+        log::debug!(
+            "s3://{}/{} failed its condition on a retry, but already holds what shuk wrote",
+            bucket,
+            key
+        );
+        return Ok(());
+    }
+    Err(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conditions_follow_what_was_found() {
+        let create = WriteCondition::for_remote(&Remote::Missing);
+        assert_eq!(create.if_none_match().as_deref(), Some("*"));
+        assert_eq!(create.if_match(), None);
+
+        let replace = WriteCondition::for_remote(&Remote::Different(Some("\"abc\"".into())));
+        assert_eq!(replace.if_none_match(), None);
+        assert_eq!(replace.if_match().as_deref(), Some("\"abc\""));
+
+        let overwrite = WriteCondition::for_remote(&Remote::Different(None));
+        assert_eq!(overwrite, WriteCondition::Overwrite);
+        assert_eq!(overwrite.if_none_match(), None);
+        assert_eq!(overwrite.if_match(), None);
+    }
+
+    #[tokio::test]
+    async fn a_retry_that_finds_its_own_write_succeeds() {
+        use aws_sdk_s3::error::{ErrorMetadata, SdkError};
+        use aws_sdk_s3::operation::put_object::PutObjectError;
+        use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+        use aws_smithy_types::body::SdkBody;
+
+        let client = aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version_latest()
+                .region(aws_sdk_s3::config::Region::new("us-east-1"))
+                .build(),
+        );
+        let failure = |code: &str, status: u16| {
+            let error = SdkError::service_error(
+                PutObjectError::generic(ErrorMetadata::builder().code(code).build()),
+                HttpResponse::try_from(
+                    http::Response::builder()
+                        .status(status)
+                        .body(SdkBody::empty())
+                        .unwrap(),
+                )
+                .unwrap(),
+            );
+            S3OperationError::from_sdk_error("PutObject", &client, "foo", Some("shuk/a"), &error)
+        };
+        let check = |code, status, is_ours: bool| {
+            check_write_error(
+                failure(code, status),
+                "foo",
+                "shuk/a",
+                &WriteCondition::Create,
+                async move { is_ours },
+            )
+        };
+
+        assert!(check("PreconditionFailed", 412, true).await.is_ok());
+        let theirs = check("PreconditionFailed", 412, false).await.unwrap_err();
+        assert!(is_failed(&theirs));
+        // Only a failed condition can be a write that went through
+        let other = check("AccessDenied", 403, true).await.unwrap_err();
+        assert!(!is_failed(&other));
+    }
+
+    #[test]
+    fn only_failed_conditions_are_precondition_failures() {
+        assert!(is_precondition_failure(Some("PreconditionFailed")));
+        assert!(is_precondition_failure(Some("ConditionalRequestConflict")));
+        assert!(!is_precondition_failure(Some("AccessDenied")));
+        assert!(!is_precondition_failure(None));
+    }
+}
@@ -0,0 +1,87 @@
+// Retrying failed requests
+use std::time::Duration;
+
+use aws_config::retry::RetryConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::utils;
+
+// How often a request or a part is retried when `max_retries` is not configured
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+// The backoff doubles with every retry of a part, but never goes above the cap
+const BASE_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum RetryMode {
+    #[default]
+    Standard,
+    // Also slows down sending requests when S3 starts throttling
+    Adaptive,
+}
+
+pub fn max_retries(shuk_config: &utils::Config) -> u32 {
+    shuk_config.max_retries.unwrap_or(DEFAULT_MAX_RETRIES)
+}
+
+// The retry settings for the SDK
+pub fn sdk_retry_config(shuk_config: &utils::Config) -> RetryConfig {
+    let retry_config = match shuk_config.retry_mode.unwrap_or_default() {
+        RetryMode::Standard => RetryConfig::standard(),
+        RetryMode::Adaptive => RetryConfig::adaptive(),
+    };
+    // The first attempt counts as an attempt too
+    retry_config.with_max_attempts(max_retries(shuk_config).saturating_add(1))
+}
+
+// For requests shuk retries itself, so the SDK does not retry every one of those attempts again
+pub fn single_attempt_config(shuk_config: &utils::Config) -> RetryConfig {
+    sdk_retry_config(shuk_config).with_max_attempts(1)
+}
+
+// How long to wait before retrying a part for the `retry`th time. This is "full jitter": a random
+// time up to the exponential backoff, so parts that failed together do not all come back at once.
+pub fn backoff(retry: u32) -> Duration {
+    let ceiling = BASE_DELAY
+        .saturating_mul(
+            1u32.checked_shl(retry.saturating_sub(1))
+                .unwrap_or(u32::MAX),
+        )
+        .min(MAX_DELAY);
+    ceiling.mul_f64(fastrand::f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_grows_and_stays_under_the_cap() {
+        for _ in 0..100 {
+            assert!(backoff(1) <= Duration::from_secs(1));
+            assert!(backoff(3) <= Duration::from_secs(4));
+            assert!(backoff(40) <= MAX_DELAY);
+        }
+    }
+
+    #[test]
+    fn retry_settings_come_from_the_config() {
+        let mut config = utils::Config::from_toml("");
+        let retry_config = sdk_retry_config(&config);
+        assert_eq!(retry_config.max_attempts(), DEFAULT_MAX_RETRIES + 1);
+        assert_eq!(retry_config.mode(), aws_config::retry::RetryMode::Standard);
+
+        config.max_retries = Some(0);
+        config.retry_mode = Some(RetryMode::Adaptive);
+        let retry_config = sdk_retry_config(&config);
+        assert_eq!(retry_config.max_attempts(), 1);
+        assert_eq!(retry_config.mode(), aws_config::retry::RetryMode::Adaptive);
+
+        // Parts are retried `max_retries` times by shuk, with one SDK attempt each
+        config.max_retries = Some(5);
+        let retry_config = single_attempt_config(&config);
+        assert_eq!(retry_config.max_attempts(), 1);
+        assert_eq!(retry_config.mode(), aws_config::retry::RetryMode::Adaptive);
+    }
+}
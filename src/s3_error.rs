@@ -5,6 +5,19 @@ use aws_sdk_s3::{
 };
 use std::{error::Error, fmt};
 
+// Error codes that mean S3 is busy or had a hiccup, and the same request can succeed a moment later
+const TRANSIENT_ERROR_CODES: &[&str] = &[
+    "SlowDown",
+    "Throttling",
+    "ThrottlingException",
+    "RequestLimitExceeded",
+    "TooManyRequestsException",
+    "RequestThrottled",
+    "RequestTimeout",
+    "InternalError",
+    "ServiceUnavailable",
+];
+
 #[derive(Debug)]
 pub struct S3OperationError {
     operation: &'static str,
@@ -17,6 +30,7 @@ pub struct S3OperationError {
     message: Option<String>,
     request_id: Option<String>,
     extended_request_id: Option<String>,
+    retryable: bool,
     details: String,
 }
 
@@ -39,6 +53,7 @@ impl S3OperationError {
         let reported_region = response
             .and_then(|response| response.headers().get("x-amz-bucket-region"))
             .map(str::to_string);
+        let status = response.map(|response| response.status().as_u16());
 
         Self {
             operation,
@@ -46,11 +61,12 @@ impl S3OperationError {
             key: key.map(str::to_string),
             configured_region,
             reported_region,
-            status: response.map(|response| response.status().as_u16()),
+            status,
             code: error.code().map(str::to_string),
             message: error.message().map(str::to_string),
             request_id: error.request_id().map(str::to_string),
             extended_request_id: error.extended_request_id().map(str::to_string),
+            retryable: is_transient(error, status),
             details: format!("{}", DisplayErrorContext(error)),
         }
     }
@@ -70,9 +86,39 @@ impl S3OperationError {
         self.configured_region.as_deref()
     }
 
+    pub fn code(&self) -> Option<&str> {
+        self.code.as_deref()
+    }
+
     pub fn extended_request_id(&self) -> Option<&str> {
         self.extended_request_id.as_deref()
     }
+
+    // Throttling, server errors and network trouble are worth another try. Anything else, such as
+    // an authorization failure, fails the same way every time.
+    pub fn is_retryable(&self) -> bool {
+        self.retryable
+    }
+}
+
+fn is_transient<E>(error: &SdkError<E>, status: Option<u16>) -> bool
+where
+    E: ProvideErrorMetadata,
+{
+    match error {
+        SdkError::TimeoutError(_) => true,
+        // A response that could not be read is only worth another try if S3 was struggling, a
+        // successful response that can not be parsed fails the same way every time
+        SdkError::ResponseError(_) => matches!(status, Some(429) | Some(500..=599)),
+        SdkError::DispatchFailure(failure) => !failure.is_user(),
+        SdkError::ServiceError(_) => {
+            matches!(status, Some(429) | Some(500..=599))
+                || error
+                    .code()
+                    .is_some_and(|code| TRANSIENT_ERROR_CODES.contains(&code))
+        }
+        _ => false,
+    }
 }
 
 impl fmt::Display for S3OperationError {
@@ -186,4 +232,77 @@ mod tests {
 
         assert_eq!(details.retry_region(), None);
     }
+
+    #[test]
+    fn throttling_and_server_errors_are_retryable() {
+        let client = client_in("us-east-1");
+        let slow_down = SdkError::service_error(
+            HeadObjectError::generic(
+                aws_sdk_s3::error::ErrorMetadata::builder()
+                    .code("SlowDown")
+                    .build(),
+            ),
+            response(503, None),
+        );
+        let details = S3OperationError::from_sdk_error(
+            "UploadPart",
+            &client,
+            "example-bucket",
+            Some("video.mp4"),
+            &slow_down,
+        );
+        assert!(details.is_retryable());
+
+        let timeout: SdkError<HeadObjectError, HttpResponse> =
+            SdkError::timeout_error(std::io::Error::other("timed out"));
+        let details = S3OperationError::from_sdk_error(
+            "UploadPart",
+            &client,
+            "example-bucket",
+            Some("video.mp4"),
+            &timeout,
+        );
+        assert!(details.is_retryable());
+    }
+
+    #[test]
+    fn unreadable_responses_are_only_retried_for_server_errors() {
+        let client = client_in("us-east-1");
+        let unreadable = |status| -> SdkError<HeadObjectError, HttpResponse> {
+            SdkError::response_error(
+                std::io::Error::other("unexpected body"),
+                response(status, None),
+            )
+        };
+        for (status, retryable) in [(200, false), (404, false), (429, true), (502, true)] {
+            let details = S3OperationError::from_sdk_error(
+                "UploadPart",
+                &client,
+                "example-bucket",
+                Some("video.mp4"),
+                &unreadable(status),
+            );
+            assert_eq!(details.is_retryable(), retryable, "HTTP {}", status);
+        }
+    }
+
+    #[test]
+    fn auth_errors_are_not_retryable() {
+        let error = SdkError::service_error(
+            HeadObjectError::generic(
+                aws_sdk_s3::error::ErrorMetadata::builder()
+                    .code("InvalidAccessKeyId")
+                    .build(),
+            ),
+            response(403, None),
+        );
+        let details = S3OperationError::from_sdk_error(
+            "UploadPart",
+            &client_in("us-east-1"),
+            "example-bucket",
+            Some("video.mp4"),
+            &error,
+        );
+        assert!(!details.is_retryable());
+    }
 }
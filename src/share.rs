@@ -0,0 +1,895 @@
+// The exists/compare/upload/presign pipeline for a single file, so shuk can run it for every
+// file passed on the command line and report on all of them at the end.
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use aws_sdk_s3::Client;
+use colored::Colorize;
+
+use crate::archive;
+use crate::compression::{self, Compression};
+use crate::conflict::{self, Resolution};
+use crate::content_addressed;
+use crate::disposition;
+use crate::encryption;
+use crate::file_management::{self, Remote};
+use crate::object_lock;
+use crate::precondition::WriteCondition;
+use crate::sse::{self, ServerSideEncryption};
+use crate::upload;
+use crate::utils;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    // The file was uploaded
+    Uploaded,
+    // An identical file was already in S3, it was only presigned again
+    Presigned,
+    // An identical file was already in S3 and no presigned URL was asked for
+    AlreadyUploaded,
+    // A different file was at the key and was replaced
+    Overwritten,
+    // A different file was at the key, the file was uploaded under a numbered or timestamped name
+    Renamed,
+    // A different file was at the key and was left alone
+    Skipped,
+    // A different file was at the key and `on_conflict` says to fail
+    Conflict,
+    Failed(String),
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Uploaded => write!(f, "uploaded"),
+            Action::Presigned => write!(f, "already uploaded, presigned"),
+            Action::AlreadyUploaded => write!(f, "already uploaded"),
+            Action::Overwritten => write!(f, "overwritten"),
+            Action::Renamed => write!(f, "uploaded under a new name"),
+            Action::Skipped => write!(f, "skipped, a different file is there"),
+            Action::Conflict => write!(f, "failed: a different file is there"),
+            Action::Failed(error) => write!(f, "failed: {}", error),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ShareResult {
+    pub key: String,
+    pub action: Action,
+    pub url: Option<String>,
+}
+
+impl ShareResult {
+    pub fn failed(key: &str, error: &anyhow::Error) -> Self {
+        Self {
+            key: key.to_string(),
+            action: Action::Failed(error.to_string()),
+            url: None,
+        }
+    }
+
+    pub fn is_failed(&self) -> bool {
+        matches!(self.action, Action::Failed(_) | Action::Conflict)
+    }
+}
+
+// Where a file goes once it is known what to do about a different file at its key
+enum Destination {
+    Upload {
+        key_full: String,
+        remote: Remote,
+        action: Action,
+    },
+    Stop(ShareResult),
+}
+
+// Applies `on_conflict` to the different file at `key_full`. `plain_name` is the name the key was
+// made from, before encryption added its extension, so a renamed file keeps its extensions in order.
+async fn resolve_conflict(
+    client: &mut Client,
+    sdk_config: &aws_config::SdkConfig,
+    plain_name: &str,
+    key_full: String,
+    remote: Remote,
+    shuk_config: &utils::Config,
+) -> Result<Destination, anyhow::Error> {
+    let bucket = &shuk_config.bucket_name;
+    let uri = format!("s3://{}/{}", bucket, key_full);
+    let resolution = tokio::task::block_in_place(|| conflict::decide(shuk_config, &uri))?;
+    log::trace!("Resolving the conflict at {} with: {:?}", &uri, resolution);
+    // The configured policy, which is `prompt` when the outcome was picked at the prompt
+    let policy = shuk_config
+        .on_conflict
+        .map_or_else(String::new, |on_conflict| on_conflict.to_string());
+
+    let stop = |key: String, action| {
+        Destination::Stop(ShareResult {
+            key,
+            action,
+            url: None,
+        })
+    };
+    match resolution {
+        Resolution::Overwrite => {
+            println!(
+                "{} | {} holds a different file, it will be overwritten",
+                "NOTE".yellow(),
+                uri
+            );
+            Ok(Destination::Upload {
+                key_full,
+                remote,
+                action: Action::Overwritten,
+            })
+        }
+        Resolution::Fail { no_terminal } => {
+            println!(
+                "❌ | {} holds a different file, it was not overwritten (on_conflict = {}{})",
+                uri,
+                policy,
+                if no_terminal {
+                    ", no terminal, falling back to fail"
+                } else {
+                    ""
+                }
+            );
+            Ok(stop(key_full, Action::Conflict))
+        }
+        Resolution::Skip => {
+            println!(
+                "⏭️  | {} holds a different file, skipping this one (on_conflict = {})",
+                uri, policy
+            );
+            Ok(stop(key_full, Action::Skipped))
+        }
+        Resolution::Rename => {
+            let sse = sse::settings(shuk_config);
+            let key_for =
+                |name: &str| object_key(shuk_config, &encryption::object_name(shuk_config, name));
+            let mut renamed = None;
+            for number in 1..=conflict::MAX_NUMBERED_NAMES {
+                let candidate = key_for(&conflict::numbered_name(plain_name, number));
+                if !object_exists(client, sdk_config, bucket, &candidate, &sse).await? {
+                    renamed = Some(candidate);
+                    break;
+                }
+            }
+            // The upload only creates the object, so even a taken timestamp is never overwritten
+            let renamed = renamed.unwrap_or_else(|| {
+                key_for(&conflict::timestamped_name(
+                    plain_name,
+                    chrono::Local::now(),
+                ))
+            });
+            println!(
+                "✏️  | {} holds a different file, uploading this one as s3://{}/{} instead",
+                uri, bucket, renamed
+            );
+            Ok(Destination::Upload {
+                key_full: renamed,
+                remote: Remote::Missing,
+                action: Action::Renamed,
+            })
+        }
+    }
+}
+
+// A local file that is going to be shared, and the name it gets in the bucket (before the prefix)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub key_name: String,
+}
+
+// Turns the paths from the command line into the list of files to share. Files keep just their
+// file name as the key. With `recursive`, directories are walked and every file in them keeps its
+// path relative to the directory, so `shuk -r ./dist` puts `./dist/js/app.js` at `js/app.js`.
+// Anything that can not be shared is returned as a failed result instead.
+pub fn collect_sources(
+    paths: &[PathBuf],
+    recursive: bool,
+    shuk_config: &utils::Config,
+) -> (Vec<SourceFile>, Vec<ShareResult>) {
+    let mut sources = Vec::new();
+    let mut failures = Vec::new();
+    let mut fail = |name: &Path, error: anyhow::Error| {
+        eprintln!("Error: {}", error);
+        failures.push(ShareResult::failed(&name.to_string_lossy(), &error));
+    };
+
+    for path in paths {
+        if !path.is_dir() {
+            // NOTE: Getting just the key (file name)
+            match path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map(|s| s.trim_matches('"'))
+            {
+                Some(key_name) => sources.push(SourceFile {
+                    path: path.clone(),
+                    key_name: key_name.to_string(),
+                }),
+                None => fail(
+                    path,
+                    anyhow::anyhow!("Invalid filename provided: {:?}", path),
+                ),
+            }
+            continue;
+        }
+
+        if !recursive {
+            fail(
+                path,
+                anyhow::anyhow!(
+                    "{:?} is a directory. Pass --recursive to upload everything in it",
+                    path
+                ),
+            );
+            continue;
+        }
+
+        log::trace!("Walking directory {:?}", &path);
+        for entry in walkdir::WalkDir::new(path)
+            .follow_links(true)
+            .sort_by_file_name()
+        {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(error) => {
+                    let name = error.path().unwrap_or(path).to_path_buf();
+                    fail(
+                        &name,
+                        anyhow::anyhow!("Unable to read {:?}: {}", name, error),
+                    );
+                    continue;
+                }
+            };
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            // S3 keys always use `/`, whatever the local path separator is
+            let relative = entry.path().strip_prefix(path).unwrap_or(entry.path());
+            let key_name = relative
+                .components()
+                .map(|component| component.as_os_str().to_str())
+                .collect::<Option<Vec<_>>>()
+                .map(|components| components.join("/"));
+            match key_name {
+                Some(key_name) => {
+                    log::trace!(
+                        "Found {:?}, it will be uploaded as {}",
+                        entry.path(),
+                        object_key(shuk_config, &key_name)
+                    );
+                    sources.push(SourceFile {
+                        path: entry.path().to_path_buf(),
+                        key_name,
+                    })
+                }
+                None => fail(
+                    entry.path(),
+                    anyhow::anyhow!(
+                        "{:?} is not valid UTF-8 and can not be used as a key",
+                        entry.path()
+                    ),
+                ),
+            }
+        }
+    }
+
+    (sources, failures)
+}
+
+// The full object key, with the bucket prefix in front of the name
+pub fn object_key(shuk_config: &utils::Config, name: &str) -> String {
+    format!(
+        "{}{}",
+        shuk_config.bucket_prefix.as_deref().unwrap_or(""),
+        name
+    )
+}
+
+// Checks whether the key already exists. If S3 tells us the bucket lives in a different region,
+// `client` is replaced with one for the right region and the check is tried once more. Every file
+// after that uses the new client, so the extra request only happens once per run.
+pub async fn object_exists(
+    client: &mut Client,
+    sdk_config: &aws_config::SdkConfig,
+    bucket: &str,
+    key: &str,
+    sse: &ServerSideEncryption,
+) -> Result<bool, anyhow::Error> {
+    let fail_file_check = |error: crate::s3_error::S3OperationError| {
+        if let Some(request_id) = error.extended_request_id() {
+            log::debug!("S3 extended request ID: {request_id}");
+        }
+        anyhow::anyhow!(
+            "Could not determine whether s3://{}/{} exists. Details: {}. Refusing to upload because Shuk could not safely determine whether it would replace an existing object.",
+            bucket,
+            key,
+            error
+        )
+    };
+
+    match file_management::file_exists_in_s3(client, bucket, key, sse).await {
+        Ok(exists) => Ok(exists),
+        Err(error) => {
+            let Some(bucket_region) = error.retry_region().map(str::to_string) else {
+                return Err(fail_file_check(error));
+            };
+            switch_region(client, sdk_config, bucket, &error, bucket_region);
+            file_management::file_exists_in_s3(client, bucket, key, sse)
+                .await
+                .map_err(fail_file_check)
+        }
+    }
+}
+
+// Replaces `client` with one for the region the bucket is actually in, and tells the user about it
+pub fn switch_region(
+    client: &mut Client,
+    sdk_config: &aws_config::SdkConfig,
+    bucket: &str,
+    error: &crate::s3_error::S3OperationError,
+    bucket_region: String,
+) {
+    let configured_region = error.configured_region().unwrap_or("unknown");
+    eprintln!(
+        "Warning: AWS selected region `{configured_region}`, but bucket `{bucket}` is in `{bucket_region}`."
+    );
+    eprintln!("Retrying automatically in `{bucket_region}`.");
+    eprintln!(
+        "Tip: Update your AWS region setting or set fallback_region = \"{bucket_region}\" in the Shuk configuration to avoid this extra request."
+    );
+
+    *client = utils::s3_client_for_region(sdk_config, bucket_region);
+}
+
+pub async fn share_file(
+    client: &mut Client,
+    sdk_config: &aws_config::SdkConfig,
+    file_name: &Path,
+    key_name: &str,
+    upload_only: bool,
+    shuk_config: &utils::Config,
+) -> Result<ShareResult, anyhow::Error> {
+    let local_name = file_name
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let name = disposition::download_name(shuk_config, key_name, &local_name);
+    let plain_name = key_name;
+    let key_name = &encryption::object_name(shuk_config, key_name);
+    let key_full = object_key(shuk_config, key_name);
+    log::trace!("Sharing {:?} as {}", &file_name, &key_full);
+    let sse = sse::settings(shuk_config);
+
+    let object_exists = object_exists(
+        client,
+        sdk_config,
+        &shuk_config.bucket_name,
+        &key_full,
+        &sse,
+    )
+    .await?;
+
+    // Calculate partial MD5 of the local file
+    let md5_of_file = file_management::calculate_partial_hash(file_name)?;
+    let file_size = md5_of_file.file_size;
+    // Prep the tags
+    let file_tags =
+        file_management::ObjectTags::new(md5_of_file.start_hash, md5_of_file.end_hash, shuk_config);
+    log::trace!("File tags defined: {:#?}", &file_tags);
+
+    let compare_error = |error: &dyn std::fmt::Display| {
+        anyhow::anyhow!(
+            "Could not compare the local file with s3://{}/{}: {}",
+            shuk_config.bucket_name,
+            key_full,
+            error
+        )
+    };
+    let head = if object_exists {
+        file_management::get_file_metadata(client, &shuk_config.bucket_name, &key_full, &sse)
+            .await
+            .map_err(|error| compare_error(&error))?
+    } else {
+        None
+    };
+
+    let remote = if !object_exists {
+        Remote::Missing
+    } else if content_addressed::is_enabled(shuk_config) {
+        // The key is the hash of the whole file, so the object has the same contents
+        log::trace!(
+            "{} is already there, it only needs to be presigned",
+            &key_full
+        );
+        Remote::Identical
+    } else {
+        file_management::quick_compare(
+            file_name,
+            &shuk_config.bucket_name,
+            key_full.as_str(),
+            &file_tags,
+            head.as_ref(),
+            client,
+        )
+        .await
+        .map_err(|error| compare_error(&error))?
+    };
+    // The object stays as it is, so its retention is what the shared file gets
+    if remote == Remote::Identical {
+        if let Some(retention) = head.as_ref().and_then(object_lock::describe) {
+            println!("🔒 | The object in S3 is locked: {}", retention);
+        }
+    }
+    let (key_full, remote, action) = match remote {
+        Remote::Different(_) => {
+            match resolve_conflict(
+                client,
+                sdk_config,
+                plain_name,
+                key_full,
+                remote,
+                shuk_config,
+            )
+            .await?
+            {
+                Destination::Upload {
+                    key_full,
+                    remote,
+                    action,
+                } => (key_full, remote, action),
+                Destination::Stop(result) => return Ok(result),
+            }
+        }
+        Remote::Identical => (key_full, remote, Action::Presigned),
+        Remote::Missing => (key_full, remote, Action::Uploaded),
+    };
+    let just_upload = remote == Remote::Identical;
+
+    // Object Lock settings only apply when an object is created
+    if just_upload && object_lock::is_requested(shuk_config) {
+        println!(
+            "{} | s3://{}/{} is already there and is not uploaded again, the Object Lock settings are not applied to it",
+            "NOTE".yellow(),
+            shuk_config.bucket_name,
+            key_full
+        );
+    }
+
+    // Upload-only early exit: file already exists and matches
+    if upload_only && just_upload {
+        log::trace!("Upload-only mode: file already exists in S3 and matches, no action needed.");
+        println!("========================================");
+        println!("✅ | File already exists in S3: {}", key_name);
+        println!("✅ | No action taken (upload-only mode)");
+        println!("========================================");
+        return Ok(ShareResult {
+            key: key_full,
+            action: Action::AlreadyUploaded,
+            url: None,
+        });
+    }
+
+    let compression = compression::for_upload(shuk_config);
+    let url = if (encryption::is_enabled(shuk_config) || compression != Compression::None)
+        && !just_upload
+    {
+        // The encrypted or compressed size is not known up front, so it goes up as a stream. The
+        // object keeps the size of the local file, which is what the next compare looks at.
+        log::trace!(
+            "Encrypting or compressing {:?} on the way to S3",
+            &file_name
+        );
+        let head = file_management::read_head(file_name)?;
+        let mut source = stream_source(
+            File::open(file_name)?,
+            name,
+            &head,
+            compression,
+            shuk_config,
+        )?;
+        source.attributes.metadata.insert(
+            file_management::SOURCE_SIZE_METADATA.into(),
+            file_size.to_string(),
+        );
+        source
+            .attributes
+            .metadata
+            .extend(content_addressed::name_metadata(shuk_config, name));
+        source.attributes.condition = WriteCondition::for_remote(&remote);
+        upload::upload_stream(client, source, &key_full, upload_only, shuk_config).await?
+    } else {
+        upload::upload_object(
+            client,
+            file_name,
+            &key_full,
+            file_tags,
+            &remote,
+            upload_only,
+            shuk_config,
+        )
+        .await?
+    };
+
+    Ok(ShareResult {
+        key: key_full,
+        action,
+        url,
+    })
+}
+
+// There is nothing to compare stdin against until it has been read, so it is always uploaded.
+// The partial hashes are calculated while it streams and tagged once it is in S3. `name` is what
+// stdin is called, which is also `key_name` unless the key is random.
+pub async fn share_stdin(
+    client: &mut Client,
+    sdk_config: &aws_config::SdkConfig,
+    name: &str,
+    key_name: &str,
+    upload_only: bool,
+    shuk_config: &utils::Config,
+) -> Result<ShareResult, anyhow::Error> {
+    // The type of the data can only be told from its first bytes, which are put back in front
+    let mut stdin = io::stdin();
+    let mut head = Vec::with_capacity(file_management::SNIFF_SIZE);
+    tokio::task::block_in_place(|| {
+        stdin
+            .by_ref()
+            .take(file_management::SNIFF_SIZE as u64)
+            .read_to_end(&mut head)
+    })?;
+    let source = stream_source(
+        io::Cursor::new(head.clone()).chain(stdin),
+        disposition::download_name(shuk_config, key_name, name),
+        &head,
+        compression::for_upload(shuk_config),
+        shuk_config,
+    )?;
+    share_stream(
+        client,
+        sdk_config,
+        source,
+        key_name,
+        upload_only,
+        shuk_config,
+    )
+    .await
+}
+
+// The archive is packed while it uploads, so just like stdin there is nothing to compare against
+// and it is always uploaded
+pub async fn share_archive(
+    client: &mut Client,
+    sdk_config: &aws_config::SdkConfig,
+    dir: &Path,
+    key_name: &str,
+    format: archive::ArchiveFormat,
+    upload_only: bool,
+    shuk_config: &utils::Config,
+) -> Result<ShareResult, anyhow::Error> {
+    log::trace!("Sharing {:?} as a {} archive", &dir, format.extension());
+    // The archive is compressed already, and its name says what it is
+    let name = archive::archive_name(dir, format)?;
+    let source = stream_source(
+        archive::archive_reader(dir, format)?,
+        disposition::download_name(shuk_config, key_name, &name),
+        &[],
+        Compression::None,
+        shuk_config,
+    )?;
+    share_stream(
+        client,
+        sdk_config,
+        source,
+        key_name,
+        upload_only,
+        shuk_config,
+    )
+    .await
+}
+
+// Hashes the data as it goes by, so the tags always describe the original, then compresses and
+// encrypts it on the way to S3 if either is turned on. `name` is what the download is called and
+// `head` the start of the data, to tell its Content-Type by.
+fn stream_source<R: Read + Send + 'static>(
+    reader: R,
+    name: &str,
+    head: &[u8],
+    compression: Compression,
+    shuk_config: &utils::Config,
+) -> Result<upload::StreamSource<Box<dyn Read + Send>>, anyhow::Error> {
+    let reader = file_management::HashingReader::new(reader);
+    let hasher = reader.hasher();
+    let download_name = encryption::object_name(shuk_config, name);
+    let mut attributes = upload::ObjectAttributes {
+        content_type: Some(file_management::content_type(shuk_config, name, head)),
+        content_disposition: disposition::for_object(shuk_config, &download_name),
+        metadata: shuk_config.metadata.clone().into_iter().collect(),
+        ..Default::default()
+    };
+
+    let reader: Box<dyn Read + Send> = if encryption::is_enabled(shuk_config) {
+        // Nothing but `shuk decrypt` can read what is inside
+        attributes.content_type = Some(file_management::content_type(
+            shuk_config,
+            &download_name,
+            &[],
+        ));
+        Box::new(encryption::encrypt_reader(reader, shuk_config)?)
+    } else if let Some(content_encoding) = compression.content_encoding() {
+        // Browsers only decompress on the fly if they also know what is inside
+        attributes.content_encoding = Some(content_encoding.to_string());
+        Box::new(compression::compress_reader(reader, compression)?)
+    } else {
+        Box::new(reader)
+    };
+    Ok(upload::StreamSource {
+        reader,
+        hasher,
+        attributes,
+        name: download_name,
+    })
+}
+
+async fn share_stream<R: Read>(
+    client: &mut Client,
+    sdk_config: &aws_config::SdkConfig,
+    mut source: upload::StreamSource<R>,
+    key_name: &str,
+    upload_only: bool,
+    shuk_config: &utils::Config,
+) -> Result<ShareResult, anyhow::Error> {
+    let plain_name = key_name;
+    let key_name = &encryption::object_name(shuk_config, key_name);
+    let key_full = object_key(shuk_config, key_name);
+    let sse = sse::settings(shuk_config);
+    let remote = if object_exists(
+        client,
+        sdk_config,
+        &shuk_config.bucket_name,
+        &key_full,
+        &sse,
+    )
+    .await?
+    {
+        // Its ETag, so only this object is replaced and not one that was put there since
+        file_management::get_file_metadata(client, &shuk_config.bucket_name, &key_full, &sse)
+            .await?
+            .map_or(Remote::Missing, |head| {
+                Remote::Different(head.e_tag().map(str::to_string))
+            })
+    } else {
+        Remote::Missing
+    };
+    // There is nothing to compare stdin or an archive with, whatever is there is a different file
+    let (key_full, remote, action) = match remote {
+        Remote::Missing => (key_full, remote, Action::Uploaded),
+        _ => match resolve_conflict(
+            client,
+            sdk_config,
+            plain_name,
+            key_full,
+            remote,
+            shuk_config,
+        )
+        .await?
+        {
+            Destination::Upload {
+                key_full,
+                remote,
+                action,
+            } => (key_full, remote, action),
+            Destination::Stop(result) => return Ok(result),
+        },
+    };
+    source.attributes.condition = WriteCondition::for_remote(&remote);
+
+    let url = upload::upload_stream(client, source, &key_full, upload_only, shuk_config).await?;
+
+    Ok(ShareResult {
+        key: key_full,
+        action,
+        url,
+    })
+}
+
+// A plain text table with one row per file: the key, what shuk did with it, and the URL
+pub fn format_summary(results: &[ShareResult]) -> String {
+    let key_width = results
+        .iter()
+        .map(|result| result.key.chars().count())
+        .chain(["KEY".len()])
+        .max()
+        .unwrap_or_default();
+    let action_width = results
+        .iter()
+        .map(|result| result.action.to_string().chars().count())
+        .chain(["ACTION".len()])
+        .max()
+        .unwrap_or_default();
+
+    let mut table = format!("{:<key_width$}  {:<action_width$}  URL\n", "KEY", "ACTION");
+    for result in results {
+        table.push_str(&format!(
+            "{:<key_width$}  {:<action_width$}  {}\n",
+            result.key,
+            result.action.to_string(),
+            result.url.as_deref().unwrap_or("-")
+        ));
+    }
+    table
+}
+
+// One line with how many files were uploaded, skipped because they were already there, or failed.
+// Files skipped because a different file was at their key are only counted if there are any.
+pub fn format_counts(results: &[ShareResult]) -> String {
+    let uploaded = results
+        .iter()
+        .filter(|result| {
+            matches!(
+                result.action,
+                Action::Uploaded | Action::Overwritten | Action::Renamed
+            )
+        })
+        .count();
+    let conflicts = results
+        .iter()
+        .filter(|result| result.action == Action::Skipped)
+        .count();
+    let failed = results.iter().filter(|result| result.is_failed()).count();
+    let skipped = results.len() - uploaded - conflicts - failed;
+    let mut counts = format!(
+        "{} uploaded, {} skipped (already uploaded), ",
+        uploaded, skipped
+    );
+    if conflicts > 0 {
+        counts.push_str(&format!(
+            "{} skipped (a different file is there), ",
+            conflicts
+        ));
+    }
+    counts.push_str(&format!("{} failed", failed));
+    counts
+}
+
+// The exit status of the run: 1 if a file failed, otherwise the status of the most serious
+// conflict, see `conflict`. Overwriting only has a status of its own if it was asked for.
+pub fn exit_status(results: &[ShareResult], shuk_config: &utils::Config) -> i32 {
+    let any = |action: Action| results.iter().any(|result| result.action == action);
+    if results
+        .iter()
+        .any(|result| matches!(result.action, Action::Failed(_)))
+    {
+        1
+    } else if any(Action::Conflict) {
+        conflict::EXIT_CONFLICT_FAILED
+    } else if any(Action::Skipped) {
+        conflict::EXIT_CONFLICT_SKIPPED
+    } else if any(Action::Renamed) {
+        conflict::EXIT_CONFLICT_RENAMED
+    } else if any(Action::Overwritten) && shuk_config.on_conflict.is_some() {
+        conflict::EXIT_CONFLICT_OVERWRITTEN
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recursive_sources_keep_relative_paths() {
+        let root = std::env::temp_dir().join(format!("shuk-walk-test-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("js/vendor")).unwrap();
+        std::fs::write(root.join("index.html"), "<html>").unwrap();
+        std::fs::write(root.join("js/app.js"), "app").unwrap();
+        std::fs::write(root.join("js/vendor/lib.js"), "lib").unwrap();
+
+        let (sources, failures) = collect_sources(
+            std::slice::from_ref(&root),
+            true,
+            &utils::Config::from_toml(""),
+        );
+        let keys: Vec<_> = sources
+            .iter()
+            .map(|source| source.key_name.as_str())
+            .collect();
+        assert!(failures.is_empty());
+        assert_eq!(keys, vec!["index.html", "js/app.js", "js/vendor/lib.js"]);
+
+        // Without --recursive a directory is an error, not an empty upload
+        let (sources, failures) = collect_sources(
+            std::slice::from_ref(&root),
+            false,
+            &utils::Config::from_toml(""),
+        );
+        assert!(sources.is_empty());
+        assert_eq!(failures.len(), 1);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn counts_group_presigned_files_as_skipped() {
+        let result = |action| ShareResult {
+            key: "k".into(),
+            action,
+            url: None,
+        };
+        let results = vec![
+            result(Action::Uploaded),
+            result(Action::Presigned),
+            result(Action::AlreadyUploaded),
+            result(Action::Failed("nope".into())),
+        ];
+        assert_eq!(
+            format_counts(&results),
+            "1 uploaded, 2 skipped (already uploaded), 1 failed"
+        );
+    }
+
+    #[test]
+    fn conflicts_have_their_own_counts_and_exit_status() {
+        let result = |action| ShareResult {
+            key: "k".into(),
+            action,
+            url: None,
+        };
+        let mut results = vec![
+            result(Action::Overwritten),
+            result(Action::Renamed),
+            result(Action::Skipped),
+            result(Action::Conflict),
+        ];
+        assert_eq!(
+            format_counts(&results),
+            "2 uploaded, 0 skipped (already uploaded), 1 skipped (a different file is there), 1 failed"
+        );
+
+        let mut shuk_config = utils::Config::from_toml("");
+        shuk_config.on_conflict = Some(conflict::OnConflict::Rename);
+        assert_eq!(exit_status(&results, &shuk_config), 3);
+        results.pop();
+        assert_eq!(exit_status(&results, &shuk_config), 4);
+        results.pop();
+        assert_eq!(exit_status(&results, &shuk_config), 5);
+        results.pop();
+        assert_eq!(exit_status(&results, &shuk_config), 6);
+        // Overwriting is what shuk always did, without the setting it is not a conflict
+        shuk_config.on_conflict = None;
+        assert_eq!(exit_status(&results, &shuk_config), 0);
+        results.push(result(Action::Failed("nope".into())));
+        assert_eq!(exit_status(&results, &shuk_config), 1);
+    }
+
+    #[test]
+    fn summary_lines_up_columns() {
+        let results = vec![
+            ShareResult {
+                key: "shuk/a.txt".into(),
+                action: Action::Uploaded,
+                url: Some("https://example.com/a".into()),
+            },
+            ShareResult {
+                key: "shuk/longer-name.iso".into(),
+                action: Action::Failed("boom".into()),
+                url: None,
+            },
+        ];
+
+        assert_eq!(
+            format_summary(&results),
+            "KEY                   ACTION        URL\n\
+             shuk/a.txt            uploaded      https://example.com/a\n\
+             shuk/longer-name.iso  failed: boom  -\n"
+        );
+    }
+}
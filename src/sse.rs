@@ -0,0 +1,243 @@
+// Server-side encryption of uploaded objects
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use aws_sdk_s3::types;
+use aws_smithy_types::base64;
+use serde::{Deserialize, Serialize};
+
+use crate::utils;
+
+// SSE-C keys are always AES-256 keys
+const CUSTOMER_KEY_LENGTH: usize = 32;
+const CUSTOMER_ALGORITHM: &str = "AES256";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum SseMode {
+    // Keys managed by S3
+    SseS3,
+    // A KMS key, `sse_kms_key_id` or the AWS managed key for S3
+    SseKms,
+    // A key from `sse_customer_key_file`
+    SseC,
+}
+
+// An SSE-C key, ready to be sent as headers
+#[derive(Clone, PartialEq, Eq)]
+pub struct CustomerKey {
+    key: String,
+    key_md5: String,
+}
+
+// The key must never end up in a log
+impl fmt::Debug for CustomerKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomerKey")
+            .field("key", &"<redacted>")
+            .field("key_md5", &self.key_md5)
+            .finish()
+    }
+}
+
+impl CustomerKey {
+    // The key file holds the 32 bytes of the key, either as they are or base64 encoded, like the
+    // output of `openssl rand 32` or `openssl rand -base64 32`
+    pub fn from_file(path: &Path) -> Result<Self, anyhow::Error> {
+        let contents = fs::read(path).map_err(|error| {
+            anyhow::anyhow!("Unable to read the SSE-C key file {:?}: {}", path, error)
+        })?;
+        Self::from_bytes(&contents).map_err(|error| anyhow::anyhow!("{:?}: {}", path, error))
+    }
+
+    fn from_bytes(contents: &[u8]) -> Result<Self, anyhow::Error> {
+        let key = if contents.len() == CUSTOMER_KEY_LENGTH {
+            contents.to_vec()
+        } else {
+            std::str::from_utf8(contents)
+                .ok()
+                .and_then(|text| base64::decode(text.trim()).ok())
+                .filter(|key| key.len() == CUSTOMER_KEY_LENGTH)
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "an SSE-C key has to be {} bytes, raw or base64 encoded",
+                        CUSTOMER_KEY_LENGTH
+                    )
+                })?
+        };
+        Ok(Self {
+            key: base64::encode(&key),
+            key_md5: base64::encode(md5::compute(&key).0),
+        })
+    }
+}
+
+// Checks the encryption settings and reads the SSE-C key
+pub fn prepare(shuk_config: &mut utils::Config) -> Result<(), anyhow::Error> {
+    let mode = shuk_config.server_side_encryption;
+    if mode != Some(SseMode::SseKms)
+        && (shuk_config.sse_kms_key_id.is_some() || shuk_config.sse_bucket_key == Some(true))
+    {
+        return Err(anyhow::anyhow!(
+            "sse_kms_key_id and sse_bucket_key only work with server_side_encryption = \"sse-kms\""
+        ));
+    }
+    match (mode, &shuk_config.sse_customer_key_file) {
+        (Some(SseMode::SseC), Some(path)) => {
+            log::trace!("Reading the SSE-C key from {:?}", path);
+            shuk_config.sse_customer_key = Some(CustomerKey::from_file(path)?);
+            Ok(())
+        }
+        (Some(SseMode::SseC), None) => Err(anyhow::anyhow!(
+            "server_side_encryption = \"sse-c\" needs the key in sse_customer_key_file"
+        )),
+        (_, Some(_)) => Err(anyhow::anyhow!(
+            "sse_customer_key_file only works with server_side_encryption = \"sse-c\""
+        )),
+        (_, None) => Ok(()),
+    }
+}
+
+// The encryption headers for a request. Whatever is not in use stays `None`, so every field can go
+// straight into the matching `set_...` of a request builder.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServerSideEncryption {
+    pub algorithm: Option<types::ServerSideEncryption>,
+    pub kms_key_id: Option<String>,
+    pub bucket_key_enabled: Option<bool>,
+    customer_key: Option<CustomerKey>,
+}
+
+impl ServerSideEncryption {
+    pub fn customer_algorithm(&self) -> Option<String> {
+        self.customer_key
+            .as_ref()
+            .map(|_| CUSTOMER_ALGORITHM.to_string())
+    }
+
+    pub fn customer_key(&self) -> Option<String> {
+        self.customer_key.as_ref().map(|key| key.key.clone())
+    }
+
+    pub fn customer_key_md5(&self) -> Option<String> {
+        self.customer_key.as_ref().map(|key| key.key_md5.clone())
+    }
+
+    // What a multipart upload was started with, so a resumed upload can tell if it changed. The
+    // SSE-C key is only represented by its MD5.
+    pub fn fingerprint(&self) -> Option<String> {
+        if let Some(key) = &self.customer_key {
+            return Some(format!("sse-c:{}", key.key_md5));
+        }
+        self.algorithm.as_ref().map(|algorithm| {
+            format!(
+                "{}:{}:{}",
+                algorithm.as_str(),
+                self.kms_key_id.as_deref().unwrap_or(""),
+                self.bucket_key_enabled.unwrap_or(false)
+            )
+        })
+    }
+}
+
+// The headers for the configuration, after `prepare` has checked it
+pub fn settings(shuk_config: &utils::Config) -> ServerSideEncryption {
+    match shuk_config.server_side_encryption {
+        None => ServerSideEncryption::default(),
+        Some(SseMode::SseS3) => ServerSideEncryption {
+            algorithm: Some(types::ServerSideEncryption::Aes256),
+            ..Default::default()
+        },
+        Some(SseMode::SseKms) => ServerSideEncryption {
+            algorithm: Some(types::ServerSideEncryption::AwsKms),
+            kms_key_id: shuk_config.sse_kms_key_id.clone(),
+            bucket_key_enabled: shuk_config.sse_bucket_key,
+            customer_key: None,
+        },
+        Some(SseMode::SseC) => ServerSideEncryption {
+            customer_key: shuk_config.sse_customer_key.clone(),
+            ..Default::default()
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn customer_keys_are_raw_or_base64() {
+        let raw = [7u8; CUSTOMER_KEY_LENGTH];
+        let from_raw = CustomerKey::from_bytes(&raw).unwrap();
+        let from_base64 =
+            CustomerKey::from_bytes(format!("{}\n", base64::encode(raw)).as_bytes()).unwrap();
+        assert_eq!(from_raw, from_base64);
+        assert_eq!(from_raw.key_md5, base64::encode(md5::compute(raw).0));
+        assert!(!format!("{:?}", from_raw).contains(&from_raw.key));
+
+        assert!(CustomerKey::from_bytes(b"too short").is_err());
+        assert!(CustomerKey::from_bytes(base64::encode([7u8; 16]).as_bytes()).is_err());
+    }
+
+    #[test]
+    fn kms_settings_become_headers() {
+        let mut shuk_config = utils::Config::from_toml(
+            "server_side_encryption = \"sse-kms\"\nsse_kms_key_id = \"alias/shuk\"\nsse_bucket_key = true",
+        );
+        prepare(&mut shuk_config).unwrap();
+        let sse = settings(&shuk_config);
+        assert_eq!(sse.algorithm, Some(types::ServerSideEncryption::AwsKms));
+        assert_eq!(sse.kms_key_id.as_deref(), Some("alias/shuk"));
+        assert_eq!(sse.bucket_key_enabled, Some(true));
+        assert_eq!(sse.customer_key(), None);
+        assert_eq!(
+            sse.fingerprint().as_deref(),
+            Some("aws:kms:alias/shuk:true")
+        );
+
+        assert_eq!(
+            settings(&utils::Config::from_toml("")),
+            ServerSideEncryption::default()
+        );
+        assert_eq!(settings(&utils::Config::from_toml("")).fingerprint(), None);
+    }
+
+    #[test]
+    fn settings_that_do_not_fit_the_mode_are_rejected() {
+        assert!(prepare(&mut utils::Config::from_toml(
+            "sse_kms_key_id = \"alias/shuk\""
+        ))
+        .is_err());
+        assert!(prepare(&mut utils::Config::from_toml(
+            "server_side_encryption = \"sse-s3\"\nsse_customer_key_file = \"key\""
+        ))
+        .is_err());
+        assert!(prepare(&mut utils::Config::from_toml(
+            "server_side_encryption = \"sse-c\""
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn customer_key_is_read_from_the_file() {
+        let path = std::env::temp_dir().join(format!("shuk-sse-test-{}", std::process::id()));
+        fs::write(&path, [9u8; CUSTOMER_KEY_LENGTH]).unwrap();
+        let mut shuk_config = utils::Config::from_toml(&format!(
+            "server_side_encryption = \"sse-c\"\nsse_customer_key_file = {:?}",
+            path
+        ));
+        let prepared = prepare(&mut shuk_config);
+        fs::remove_file(&path).unwrap();
+        prepared.unwrap();
+
+        let sse = settings(&shuk_config);
+        assert_eq!(sse.algorithm, None);
+        assert_eq!(sse.customer_algorithm().as_deref(), Some("AES256"));
+        assert_eq!(
+            sse.customer_key(),
+            Some(base64::encode([9u8; CUSTOMER_KEY_LENGTH]))
+        );
+        assert!(sse.fingerprint().unwrap().starts_with("sse-c:"));
+    }
+}
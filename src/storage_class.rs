@@ -0,0 +1,103 @@
+// Storage classes for uploads, and whether an object can be downloaded right now
+use aws_sdk_s3::operation::head_object::HeadObjectOutput;
+use aws_sdk_s3::types;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum StorageClass {
+    #[value(name = "STANDARD")]
+    Standard,
+    #[value(name = "STANDARD_IA")]
+    StandardIa,
+    #[value(name = "ONEZONE_IA")]
+    OnezoneIa,
+    #[value(name = "INTELLIGENT_TIERING")]
+    IntelligentTiering,
+    #[value(name = "GLACIER_IR")]
+    GlacierIr,
+}
+
+impl StorageClass {
+    pub fn sdk_storage_class(&self) -> types::StorageClass {
+        match self {
+            StorageClass::Standard => types::StorageClass::Standard,
+            StorageClass::StandardIa => types::StorageClass::StandardIa,
+            StorageClass::OnezoneIa => types::StorageClass::OnezoneIa,
+            StorageClass::IntelligentTiering => types::StorageClass::IntelligentTiering,
+            StorageClass::GlacierIr => types::StorageClass::GlacierIr,
+        }
+    }
+}
+
+// Why a GET of the object would fail, if it would. Archived objects can be downloaded once a
+// restore has finished, which S3 reports as `ongoing-request="false"`.
+pub fn unavailable_reason(head: &HeadObjectOutput) -> Option<String> {
+    let archived = match (head.storage_class(), head.archive_status()) {
+        (Some(types::StorageClass::Glacier), _) => "GLACIER".to_string(),
+        (Some(types::StorageClass::DeepArchive), _) => "DEEP_ARCHIVE".to_string(),
+        (_, Some(status)) => format!("INTELLIGENT_TIERING {}", status.as_str()),
+        _ => return None,
+    };
+    match head.restore() {
+        Some(restore) if restore.contains("ongoing-request=\"false\"") => None,
+        Some(_) => Some(format!(
+            "it is in the {} storage class and its restore has not finished yet",
+            archived
+        )),
+        None => Some(format!(
+            "it is in the {} storage class and has not been restored",
+            archived
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn archived_objects_need_a_finished_restore() {
+        let head = |class: types::StorageClass, restore: Option<&str>| {
+            HeadObjectOutput::builder()
+                .storage_class(class)
+                .set_restore(restore.map(str::to_string))
+                .build()
+        };
+
+        assert_eq!(
+            unavailable_reason(&HeadObjectOutput::builder().build()),
+            None
+        );
+        assert_eq!(
+            unavailable_reason(&head(types::StorageClass::GlacierIr, None)),
+            None
+        );
+        assert!(
+            unavailable_reason(&head(types::StorageClass::Glacier, None))
+                .unwrap()
+                .contains("has not been restored")
+        );
+        assert!(unavailable_reason(&head(
+            types::StorageClass::DeepArchive,
+            Some("ongoing-request=\"true\"")
+        ))
+        .unwrap()
+        .contains("has not finished"));
+        assert_eq!(
+            unavailable_reason(&head(
+                types::StorageClass::Glacier,
+                Some("ongoing-request=\"false\", expiry-date=\"Fri, 21 Dec 2012 00:00:00 GMT\"")
+            )),
+            None
+        );
+
+        let tiered = HeadObjectOutput::builder()
+            .storage_class(types::StorageClass::IntelligentTiering)
+            .archive_status(types::ArchiveStatus::DeepArchiveAccess)
+            .build();
+        assert!(unavailable_reason(&tiered)
+            .unwrap()
+            .contains("INTELLIGENT_TIERING DEEP_ARCHIVE_ACCESS"));
+    }
+}
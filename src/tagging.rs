@@ -0,0 +1,219 @@
+// Object tags and user metadata from the configuration and the command line
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+use crate::content_addressed::{self, FILE_NAME_METADATA};
+use crate::file_management::SOURCE_SIZE_METADATA;
+use crate::utils;
+
+// S3 allows 10 tags per object, and shuk needs some of them itself
+pub const MAX_TAGS: usize = 10;
+pub const RESERVED_TAGS: [&str; 3] = ["managed_by", "start_hash", "end_hash"];
+const MAX_TAG_KEY_LENGTH: usize = 128;
+const MAX_TAG_VALUE_LENGTH: usize = 256;
+
+// All user metadata of an object, keys and values, has to fit into 2KB. Room is kept for the
+// source size shuk stores on encrypted and compressed uploads.
+const MAX_METADATA_SIZE: usize = 2 * 1024;
+const RESERVED_METADATA_SIZE: usize = SOURCE_SIZE_METADATA.len() + 20;
+
+// `x-www-form-urlencoded` as S3 reads it in `x-amz-tagging`, everything but the unreserved
+// characters is escaped
+const TAGGING_ESCAPE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+// `--tag team=data` and `--meta ticket=OPS-42`. Only the first `=` splits, values may contain more.
+pub fn parse_key_value(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.to_string()))
+        }
+        _ => Err(format!("`{}` has to look like KEY=VALUE", value)),
+    }
+}
+
+// The tag set as the query string that `x-amz-tagging` expects
+pub fn query_string<'a>(tags: impl IntoIterator<Item = (&'a str, &'a str)>) -> String {
+    tags.into_iter()
+        .map(|(key, value)| {
+            format!(
+                "{}={}",
+                utf8_percent_encode(key, TAGGING_ESCAPE),
+                utf8_percent_encode(value, TAGGING_ESCAPE)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+// Checks `[tags]` and `[metadata]` against the S3 limits
+pub fn check(shuk_config: &utils::Config) -> Result<(), anyhow::Error> {
+    let available = MAX_TAGS - RESERVED_TAGS.len();
+    if shuk_config.tags.len() > available {
+        return Err(anyhow::anyhow!(
+            "{} tags are configured, but S3 allows {} per object and shuk uses {} of them, so at most {} can be added",
+            shuk_config.tags.len(),
+            MAX_TAGS,
+            RESERVED_TAGS.len(),
+            available
+        ));
+    }
+    for (key, value) in &shuk_config.tags {
+        check_tag(key, value)?;
+    }
+
+    let mut size = 0;
+    for (key, value) in &shuk_config.metadata {
+        check_metadata(key, value)?;
+        size += key.len() + value.len();
+    }
+    let reserved = if content_addressed::is_enabled(shuk_config) {
+        RESERVED_METADATA_SIZE + content_addressed::RESERVED_METADATA_SIZE
+    } else {
+        RESERVED_METADATA_SIZE
+    };
+    if size > MAX_METADATA_SIZE - reserved {
+        return Err(anyhow::anyhow!(
+            "The metadata takes up {} bytes, but S3 allows {} bytes per object and shuk needs {} of them",
+            size,
+            MAX_METADATA_SIZE,
+            reserved
+        ));
+    }
+    Ok(())
+}
+
+fn check_tag(key: &str, value: &str) -> Result<(), anyhow::Error> {
+    if RESERVED_TAGS.contains(&key) {
+        return Err(anyhow::anyhow!(
+            "The tag `{}` is set by shuk itself and can not be configured",
+            key
+        ));
+    }
+    if key.starts_with("aws:") {
+        return Err(anyhow::anyhow!(
+            "The tag `{}` uses the `aws:` prefix, which is reserved for AWS",
+            key
+        ));
+    }
+    if key.chars().count() > MAX_TAG_KEY_LENGTH {
+        return Err(anyhow::anyhow!(
+            "The tag key `{}` is longer than {} characters",
+            key,
+            MAX_TAG_KEY_LENGTH
+        ));
+    }
+    if value.chars().count() > MAX_TAG_VALUE_LENGTH {
+        return Err(anyhow::anyhow!(
+            "The value of the tag `{}` is longer than {} characters",
+            key,
+            MAX_TAG_VALUE_LENGTH
+        ));
+    }
+    // Letters, numbers and spaces in any language, and + - = . _ : / @
+    let allowed = |c: char| c.is_alphanumeric() || c == ' ' || "+-=._:/@".contains(c);
+    if !key.chars().all(allowed) || !value.chars().all(allowed) {
+        return Err(anyhow::anyhow!(
+            "The tag `{}={}` may only contain letters, numbers, spaces and + - = . _ : / @",
+            key,
+            value
+        ));
+    }
+    Ok(())
+}
+
+// The metadata goes into HTTP headers, so keys have to be valid header names and values plain
+// ASCII. S3 stores the keys in lower case.
+fn check_metadata(key: &str, value: &str) -> Result<(), anyhow::Error> {
+    if key.eq_ignore_ascii_case(SOURCE_SIZE_METADATA)
+        || key.eq_ignore_ascii_case(FILE_NAME_METADATA)
+    {
+        return Err(anyhow::anyhow!(
+            "The metadata key `{}` is set by shuk itself and can not be configured",
+            key
+        ));
+    }
+    if !key
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+    {
+        return Err(anyhow::anyhow!(
+            "The metadata key `{}` may only contain ASCII letters, numbers and - _ .",
+            key
+        ));
+    }
+    if !value.chars().all(|c| c == ' ' || c.is_ascii_graphic()) {
+        return Err(anyhow::anyhow!(
+            "The value of the metadata key `{}` may only contain printable ASCII characters",
+            key
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_are_url_encoded() {
+        assert_eq!(
+            query_string([("team", "data & ml"), ("path", "a/b=c"), ("owner", "zoë")]),
+            "team=data%20%26%20ml&path=a%2Fb%3Dc&owner=zo%C3%AB"
+        );
+        assert_eq!(
+            parse_key_value("ticket=OPS-42=a"),
+            Ok(("ticket".into(), "OPS-42=a".into()))
+        );
+        assert!(parse_key_value("ticket").is_err());
+        assert!(parse_key_value("=OPS-42").is_err());
+    }
+
+    #[test]
+    fn tags_within_the_limits_are_accepted() {
+        check(&utils::Config::from_toml(
+            "[tags]\nteam = \"data\"\nproject = \"shuk\"\n[metadata]\nticket = \"OPS-42\"",
+        ))
+        .unwrap();
+
+        let too_many: String = (0..8).map(|i| format!("tag{} = \"x\"\n", i)).collect();
+        assert!(check(&utils::Config::from_toml(&format!("[tags]\n{}", too_many))).is_err());
+        assert!(check(&utils::Config::from_toml("[tags]\nmanaged_by = \"me\"")).is_err());
+        assert!(check(&utils::Config::from_toml("[tags]\n\"aws:team\" = \"data\"")).is_err());
+        assert!(check(&utils::Config::from_toml(&format!(
+            "[tags]\nteam = \"{}\"",
+            "x".repeat(257)
+        )))
+        .is_err());
+        assert!(check(&utils::Config::from_toml("[tags]\nteam = \"data;ml\"")).is_err());
+    }
+
+    #[test]
+    fn metadata_must_fit_into_headers() {
+        assert!(check(&utils::Config::from_toml(
+            "[metadata]\n\"shuk-source-size\" = \"1\""
+        ))
+        .is_err());
+        assert!(check(&utils::Config::from_toml(
+            "[metadata]\n\"shuk-file-name\" = \"a.txt\""
+        ))
+        .is_err());
+        assert!(check(&utils::Config::from_toml("[metadata]\n\"my key\" = \"x\"")).is_err());
+        assert!(check(&utils::Config::from_toml("[metadata]\nowner = \"zoë\"")).is_err());
+        assert!(check(&utils::Config::from_toml(&format!(
+            "[metadata]\nnotes = \"{}\"",
+            "x".repeat(2048)
+        )))
+        .is_err());
+        // Content addressed objects also keep their file name
+        let notes = format!("[metadata]\nnotes = \"{}\"", "x".repeat(1700));
+        check(&utils::Config::from_toml(&notes)).unwrap();
+        assert!(check(&utils::Config::from_toml(&format!(
+            "content_addressed = true\n{}",
+            notes
+        )))
+        .is_err());
+    }
+}
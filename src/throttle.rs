@@ -0,0 +1,224 @@
+// Bandwidth limiting for uploads
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+use std::time::{Duration, Instant};
+
+use aws_sdk_s3::primitives::SdkBody;
+use aws_smithy_runtime_api::http::Request;
+use bytes::Bytes;
+use http_body::{Body, Frame, SizeHint};
+use tokio::time::Sleep;
+
+use crate::utils;
+
+// Big frames (a whole in-memory part is a single frame) are sent in slices of this size, so the
+// data goes out at an even pace instead of in bursts
+const SLICE_SIZE: usize = 16 * 1024; // 16KB
+
+// A token bucket that is allowed to go into debt. Whoever takes bytes from it is told how long to
+// wait before sending them, which spreads the rate fairly over all the bodies that share it.
+#[derive(Debug)]
+pub struct RateLimiter {
+    bytes_per_second: f64,
+    // How much can be sent at once after the upload was idle for a while
+    burst: f64,
+    state: Mutex<LimiterState>,
+}
+
+#[derive(Debug)]
+struct LimiterState {
+    available: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_second: u64) -> Self {
+        let bytes_per_second = bytes_per_second.max(1) as f64;
+        Self {
+            bytes_per_second,
+            burst: (bytes_per_second / 10.0).max(SLICE_SIZE as f64),
+            state: Mutex::new(LimiterState {
+                available: 0.0,
+                updated: Instant::now(),
+            }),
+        }
+    }
+
+    // Takes `len` bytes from the bucket and returns how long to wait before sending them
+    pub fn reserve(&self, len: usize) -> Duration {
+        self.reserve_at(len, Instant::now())
+    }
+
+    fn reserve_at(&self, len: usize, now: Instant) -> Duration {
+        // A poisoned lock only means another upload task panicked, the numbers are still fine
+        let mut state = self
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let elapsed = now.saturating_duration_since(state.updated).as_secs_f64();
+        state.available =
+            (state.available + elapsed * self.bytes_per_second).min(self.burst) - len as f64;
+        state.updated = now;
+        if state.available >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-state.available / self.bytes_per_second)
+        }
+    }
+}
+
+// The limiter for an upload, if `max_bandwidth` is set. Like curl's `--limit-rate`, 0 means no
+// limit at all.
+pub fn limiter(shuk_config: &utils::Config) -> Option<Arc<RateLimiter>> {
+    let max_bandwidth = shuk_config.max_bandwidth.filter(|rate| *rate > 0);
+    max_bandwidth.map(|bytes_per_second| {
+        log::trace!(
+            "Limiting uploads to {}/s",
+            indicatif::HumanBytes(bytes_per_second)
+        );
+        Arc::new(RateLimiter::new(bytes_per_second))
+    })
+}
+
+// For `customize().map_request(...)`: puts the request body behind the limiter
+pub fn limit_request(
+    request: Request<SdkBody>,
+    limiter: Option<Arc<RateLimiter>>,
+) -> Result<Request<SdkBody>, Infallible> {
+    let Some(limiter) = limiter else {
+        return Ok(request);
+    };
+    Ok(request.map(|body| {
+        // The data itself is not changed, so in-memory bodies can still be checksummed and retried
+        let limiter = Arc::clone(&limiter);
+        body.map_preserve_contents(move |body| {
+            SdkBody::from_body_1_x(ThrottledBody::new(body, Arc::clone(&limiter)))
+        })
+    }))
+}
+
+#[pin_project::pin_project]
+pub struct ThrottledBody<InnerBody> {
+    #[pin]
+    inner: InnerBody,
+    limiter: Arc<RateLimiter>,
+    // What is left of the current frame
+    remaining: Bytes,
+    // A slice that was paid for and goes out once `delay` is over
+    ready: Option<Bytes>,
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl<InnerBody> ThrottledBody<InnerBody> {
+    pub fn new(body: InnerBody, limiter: Arc<RateLimiter>) -> Self {
+        Self {
+            inner: body,
+            limiter,
+            remaining: Bytes::new(),
+            ready: None,
+            delay: None,
+        }
+    }
+}
+
+impl<InnerBody> Body for ThrottledBody<InnerBody>
+where
+    InnerBody: Body<Data = Bytes, Error = aws_smithy_types::body::Error>,
+{
+    type Data = Bytes;
+    type Error = aws_smithy_types::body::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let mut this = self.project();
+        loop {
+            if let Some(delay) = this.delay.as_mut() {
+                ready!(delay.as_mut().poll(cx));
+                *this.delay = None;
+            }
+            if let Some(slice) = this.ready.take() {
+                return Poll::Ready(Some(Ok(Frame::data(slice))));
+            }
+
+            if this.remaining.is_empty() {
+                match ready!(this.inner.as_mut().poll_frame(cx)) {
+                    Some(Ok(frame)) => match frame.into_data() {
+                        Ok(data) => *this.remaining = data,
+                        // Trailers are not data, they go through as they are
+                        Err(frame) => return Poll::Ready(Some(Ok(frame))),
+                    },
+                    other => return Poll::Ready(other),
+                }
+            }
+
+            let slice_len = std::cmp::min(this.remaining.len(), SLICE_SIZE);
+            let slice = this.remaining.split_to(slice_len);
+            let wait = this.limiter.reserve(slice.len());
+            *this.ready = Some(slice);
+            if !wait.is_zero() {
+                *this.delay = Some(Box::pin(tokio::time::sleep(wait)));
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.ready.is_none() && self.remaining.is_empty() && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limiter_spreads_bytes_over_time() {
+        let limiter = RateLimiter::new(1024 * 1024);
+        let start = Instant::now();
+        // The bucket starts out empty, so a second worth of data waits a second
+        let wait = limiter.reserve_at(1024 * 1024, start);
+        assert!((wait.as_secs_f64() - 1.0).abs() < 0.001, "{:?}", wait);
+        // Another part in flight at the same time queues up behind it
+        let wait = limiter.reserve_at(1024 * 1024, start);
+        assert!((wait.as_secs_f64() - 2.0).abs() < 0.001, "{:?}", wait);
+    }
+
+    #[test]
+    fn idle_time_only_builds_up_a_small_burst() {
+        let limiter = RateLimiter::new(1024 * 1024);
+        let start = Instant::now();
+        assert_eq!(limiter.reserve_at(0, start), Duration::ZERO);
+
+        // After a minute of nothing, only a tenth of a second worth of data goes out right away
+        let later = start + Duration::from_secs(60);
+        assert_eq!(limiter.reserve_at(100 * 1024, later), Duration::ZERO);
+        let wait = limiter.reserve_at(1024 * 1024, later);
+        assert!(wait > Duration::from_millis(900), "{:?}", wait);
+    }
+
+    #[tokio::test]
+    async fn throttled_body_keeps_every_byte() {
+        let data: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+        let limiter = Arc::new(RateLimiter::new(100 * 1024 * 1024));
+        let mut body = Box::pin(ThrottledBody::new(
+            SdkBody::from(data.clone()),
+            Arc::clone(&limiter),
+        ));
+
+        let mut received = Vec::new();
+        while let Some(frame) = std::future::poll_fn(|cx| body.as_mut().poll_frame(cx)).await {
+            let data = frame.unwrap().into_data().unwrap();
+            assert!(data.len() <= SLICE_SIZE);
+            received.extend_from_slice(&data);
+        }
+        assert_eq!(received, data);
+    }
+}
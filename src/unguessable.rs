@@ -0,0 +1,248 @@
+// Unguessable object keys
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use dirs::home_dir;
+use serde::{Deserialize, Serialize};
+
+use crate::constants;
+use crate::encryption;
+use crate::file_management;
+use crate::journal::FileIdentity;
+use crate::key_template;
+use crate::utils;
+
+pub fn is_enabled(shuk_config: &utils::Config) -> bool {
+    shuk_config.unguessable_keys == Some(true)
+}
+
+// Random keys and a key template can not both name the object
+pub fn check(shuk_config: &utils::Config) -> Result<(), anyhow::Error> {
+    if is_enabled(shuk_config) && shuk_config.key_template.is_some() {
+        return Err(anyhow::anyhow!(
+            "unguessable_keys and key_template both decide on the object keys, only one of them can be used"
+        ));
+    }
+    Ok(())
+}
+
+// 16 bytes straight from the operating system, all 128 bits of them random
+fn random_name() -> Result<String, anyhow::Error> {
+    let mut bytes = [0u8; 16];
+    getrandom::fill(&mut bytes)
+        .map_err(|error| anyhow::anyhow!("Unable to get random bytes for a key: {}", error))?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+// The key name for stdin or an archive called `name`, which are new every time
+pub fn object_name(shuk_config: &utils::Config, name: &str) -> Result<String, anyhow::Error> {
+    if is_enabled(shuk_config) {
+        random_name()
+    } else {
+        key_template::object_name(shuk_config, name, None)
+    }
+}
+
+// The random key a local file was shared under
+#[derive(Debug, Deserialize, Serialize)]
+pub struct KeyRecord {
+    pub bucket: String,
+    pub prefix: String,
+    pub key_name: String,
+    pub local_path: PathBuf,
+    pub identity: FileIdentity,
+    // Where this record lives on disk, not part of the record itself
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+pub fn key_dir() -> Result<PathBuf, anyhow::Error> {
+    let home_dir = home_dir().ok_or_else(|| anyhow::anyhow!("Failed to get HOME directory"))?;
+    Ok(home_dir
+        .join(".config")
+        .join(constants::CONFIG_DIR_NAME)
+        .join(constants::KEY_DIR_NAME))
+}
+
+// One record per bucket, prefix and local file, named by a hash like the upload journals
+fn record_file_name(bucket: &str, prefix: &str, local_path: &Path) -> String {
+    let digest = md5::compute(format!("{}\n{}\n{}", bucket, prefix, local_path.display()));
+    format!("{:x}.toml", digest)
+}
+
+// The key name for a local file: the one it was shared under before if it has not changed since,
+// a new random one otherwise. `None` if unguessable keys are not in use. The record is saved with
+// `KeyRecord::save`, before the file is uploaded.
+pub fn file_key(
+    shuk_config: &utils::Config,
+    local_path: &Path,
+) -> Result<Option<KeyRecord>, anyhow::Error> {
+    if !is_enabled(shuk_config) {
+        return Ok(None);
+    }
+    KeyRecord::for_file(&key_dir()?, shuk_config, local_path).map(Some)
+}
+
+// Whether the next run gives the file at `local_path` the full object key `key` again, which it
+// only does once the key is recorded. Always the case without unguessable keys.
+pub fn is_recorded(shuk_config: &utils::Config, local_path: &Path, key: &str) -> bool {
+    if !is_enabled(shuk_config) {
+        return true;
+    }
+    key_dir().is_ok_and(|dir| is_recorded_in(&dir, shuk_config, local_path, key))
+}
+
+fn is_recorded_in(dir: &Path, shuk_config: &utils::Config, local_path: &Path, key: &str) -> bool {
+    let prefix = shuk_config.bucket_prefix.as_deref().unwrap_or("");
+    let Ok(local_path) = local_path.canonicalize() else {
+        return false;
+    };
+    let path = dir.join(record_file_name(
+        &shuk_config.bucket_name,
+        prefix,
+        &local_path,
+    ));
+    match KeyRecord::load_from(&path) {
+        Ok(Some(record)) => format!("{}{}", record.prefix, record.key_name) == key,
+        _ => false,
+    }
+}
+
+impl KeyRecord {
+    fn for_file(
+        dir: &Path,
+        shuk_config: &utils::Config,
+        local_path: &Path,
+    ) -> Result<Self, anyhow::Error> {
+        let bucket = &shuk_config.bucket_name;
+        let prefix = shuk_config.bucket_prefix.as_deref().unwrap_or("");
+        let local_path = local_path.canonicalize()?;
+        let path = dir.join(record_file_name(bucket, prefix, &local_path));
+
+        let partial_hash = file_management::calculate_partial_hash(&local_path)?;
+        let identity = FileIdentity::new(
+            &fs::metadata(&local_path)?,
+            &partial_hash.start_hash,
+            &partial_hash.end_hash,
+        )?;
+
+        match Self::load_from(&path) {
+            Ok(Some(record)) if record.identity == identity => {
+                log::trace!(
+                    "{:?} has not changed since it was shared as {}",
+                    &local_path,
+                    &record.key_name
+                );
+                return Ok(record);
+            }
+            Ok(Some(_)) => log::trace!("{:?} has changed, it gets a new key", &local_path),
+            Ok(None) => {}
+            // A broken record only costs a new key
+            Err(error) => log::warn!("{}", error),
+        }
+        Ok(Self {
+            bucket: bucket.to_string(),
+            prefix: prefix.to_string(),
+            key_name: random_name()?,
+            local_path,
+            identity,
+            path,
+        })
+    }
+
+    fn load_from(path: &Path) -> Result<Option<Self>, anyhow::Error> {
+        log::trace!("Looking for a key record at {:?}", &path);
+        if !path.try_exists()? {
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(path)?;
+        let mut record: KeyRecord = toml::from_str(&contents)
+            .map_err(|error| anyhow::anyhow!("Key record {:?} is corrupted: {}", path, error))?;
+        record.path = path.to_path_buf();
+        log::trace!("Key record found: {:#?}", &record);
+        Ok(Some(record))
+    }
+
+    // A conflict made the file go up under the full object key `key` instead, which is the one the
+    // next run has to find it at
+    pub fn move_to(&mut self, shuk_config: &utils::Config, key: &str) {
+        let name = key.strip_prefix(self.prefix.as_str()).unwrap_or(key);
+        // `share_file` adds the extension of an encrypted file again
+        let extension = format!(".{}", encryption::ENCRYPTED_EXTENSION);
+        let name = if encryption::is_enabled(shuk_config) {
+            name.strip_suffix(&extension).unwrap_or(name)
+        } else {
+            name
+        };
+        self.key_name = name.to_string();
+    }
+
+    // Written to a temporary file first and then renamed, like the upload journals
+    pub fn save(&self) -> Result<(), anyhow::Error> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temp_path = self.path.with_extension("toml.tmp");
+        fs::write(&temp_path, toml::to_string_pretty(self)?)?;
+        fs::rename(&temp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_names_say_nothing_about_the_file() {
+        let name = random_name().unwrap();
+        assert_eq!(name.len(), 32);
+        assert!(name.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(name, random_name().unwrap());
+
+        let shuk_config = utils::Config::from_toml("unguessable_keys = true");
+        assert!(check(&shuk_config).is_ok());
+        assert!(check(&utils::Config::from_toml(
+            "unguessable_keys = true\nkey_template = \"{uuid}\""
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn unchanged_files_keep_their_key() {
+        let dir =
+            std::env::temp_dir().join(format!("shuk-unguessable-test-{}", std::process::id()));
+        let file = dir.join("report.pdf");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&file, b"first").unwrap();
+        let shuk_config = utils::Config::from_toml("unguessable_keys = true");
+        let records = dir.join("keys");
+
+        let first = KeyRecord::for_file(&records, &shuk_config, &file).unwrap();
+        let key = format!("{}{}", first.prefix, first.key_name);
+        assert!(!is_recorded_in(&records, &shuk_config, &file, &key));
+        first.save().unwrap();
+        assert!(is_recorded_in(&records, &shuk_config, &file, &key));
+        let again = KeyRecord::for_file(&records, &shuk_config, &file).unwrap();
+        // A rename is what the next run gets
+        let mut renamed = KeyRecord::for_file(&records, &shuk_config, &file).unwrap();
+        renamed.move_to(
+            &shuk_config,
+            &format!("{}{}-1", first.prefix, first.key_name),
+        );
+        renamed.save().unwrap();
+        let after_rename = KeyRecord::for_file(&records, &shuk_config, &file).unwrap();
+        // Another bucket is another record
+        let mut other_bucket = utils::Config::from_toml("unguessable_keys = true");
+        other_bucket.bucket_name = "bar".to_string();
+        let elsewhere = KeyRecord::for_file(&records, &other_bucket, &file).unwrap();
+        fs::write(&file, b"second").unwrap();
+        let changed = KeyRecord::for_file(&records, &shuk_config, &file).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(again.key_name, first.key_name);
+        assert_eq!(after_rename.key_name, format!("{}-1", first.key_name));
+        assert_ne!(elsewhere.key_name, first.key_name);
+        assert_ne!(changed.key_name, first.key_name);
+    }
+}
@@ -1,32 +1,63 @@
+use aws_config::retry::RetryConfig;
 use aws_sdk_s3::{
-    operation::create_multipart_upload::CreateMultipartUploadOutput,
+    error::ProvideErrorMetadata,
+    operation::create_multipart_upload::{
+        builders::CreateMultipartUploadFluentBuilder, CreateMultipartUploadOutput,
+    },
+    operation::put_object::builders::PutObjectFluentBuilder,
     primitives::SdkBody,
-    types::{CompletedMultipartUpload, CompletedPart},
+    types::{ChecksumType, CompletedMultipartUpload, CompletedPart},
     Client,
 };
 use aws_smithy_runtime_api::http::Request;
 use aws_smithy_types::byte_stream::{ByteStream, Length};
 use std::{
+    collections::HashMap,
     convert::Infallible,
     fmt::Write,
     fs::File,
-    io::prelude::*,
+    io::Read,
     path::{Path, PathBuf},
     pin::Pin,
+    sync::{Arc, Mutex},
     task::{Context, Poll},
 };
+use tokio::task::JoinSet;
 
 use bytes::Bytes;
+use colored::Colorize;
 use http_body::{Body, Frame, SizeHint};
 
-use indicatif::{ProgressBar, ProgressState, ProgressStyle};
+use indicatif::{HumanBytes, ProgressBar, ProgressState, ProgressStyle};
 
-use crate::file_management;
+use crate::checksum::{self, Checksum, ChecksumAlgorithm};
+use crate::cleanup;
+use crate::content_addressed;
+use crate::disposition;
+use crate::file_management::{self, Remote};
+use crate::journal::{FileIdentity, UploadJournal};
+use crate::key_template;
+use crate::object_lock;
+use crate::precondition::{self, WriteCondition};
+use crate::retry;
+use crate::sse::{self, ServerSideEncryption};
+use crate::throttle::{self, RateLimiter};
+use crate::unguessable;
 use crate::utils;
 
 // NOTE: Anything smaller than 5MB causes the uploads to be slow(er)
-// The PART_SIZE needs to be at least 5MB
-const PART_SIZE: u64 = 5 * 1024 * 1024; // 5MB
+// S3 requires every part (except the last one) to be at least 5MB and at most 5GB, and a single
+// multipart upload can have at most 10,000 parts.
+const MIN_PART_SIZE: u64 = 5 * 1024 * 1024; // 5MB
+const MAX_PART_SIZE: u64 = 5 * 1024 * 1024 * 1024; // 5GB
+const MAX_PARTS: u64 = 10_000;
+// The largest object S3 will store, 5TB
+const MAX_OBJECT_SIZE: u64 = 5 * 1024 * 1024 * 1024 * 1024;
+// PutObject can not take more than 5GB, so the threshold can not be set any higher than that
+const DEFAULT_MULTIPART_THRESHOLD: u64 = 4 * 1024 * 1024 * 1024; // 4GB
+
+// How many parts are in flight at once when `upload_concurrency` is not configured
+const DEFAULT_UPLOAD_CONCURRENCY: usize = 4;
 
 // NOTE: The upload with progress is from this example:
 // https://github.com/awsdocs/aws-doc-sdk-examples/blob/main/rustv1/examples/s3/src/bin/put-object-progress.rs
@@ -132,13 +163,774 @@ where
     }
 }
 
+// Works out how big each part has to be so the whole file fits into S3's 10,000 part limit.
+// Parts never get smaller than `min_part_size`, and are rounded up to a whole MB.
+pub fn calculate_part_size(
+    file_size: u64,
+    min_part_size: Option<u64>,
+) -> Result<u64, anyhow::Error> {
+    let min_part_size = min_part_size.unwrap_or(MIN_PART_SIZE);
+    if min_part_size < MIN_PART_SIZE {
+        return Err(anyhow::anyhow!(
+            "min_part_size is {}, but S3 requires parts of at least {}",
+            HumanBytes(min_part_size),
+            HumanBytes(MIN_PART_SIZE)
+        ));
+    }
+    if min_part_size > MAX_PART_SIZE {
+        return Err(anyhow::anyhow!(
+            "min_part_size is {}, but S3 does not accept parts larger than {}",
+            HumanBytes(min_part_size),
+            HumanBytes(MAX_PART_SIZE)
+        ));
+    }
+    if file_size > MAX_OBJECT_SIZE {
+        return Err(anyhow::anyhow!(
+            "The file is {}, but S3 objects can not be larger than {}",
+            HumanBytes(file_size),
+            HumanBytes(MAX_OBJECT_SIZE)
+        ));
+    }
+
+    const MB: u64 = 1024 * 1024;
+    let part_size = std::cmp::max(min_part_size, file_size.div_ceil(MAX_PARTS));
+    let part_size = std::cmp::min(part_size.div_ceil(MB) * MB, MAX_PART_SIZE);
+    log::trace!(
+        "Using a part size of {} for a file of {} ({} parts)",
+        &part_size,
+        &file_size,
+        file_size.div_ceil(part_size)
+    );
+    Ok(part_size)
+}
+
+// Files bigger than this are uploaded with a multipart upload
+pub fn multipart_threshold(shuk_config: &utils::Config) -> Result<u64, anyhow::Error> {
+    let threshold = shuk_config
+        .multipart_threshold
+        .unwrap_or(DEFAULT_MULTIPART_THRESHOLD);
+    if threshold > MAX_PART_SIZE {
+        return Err(anyhow::anyhow!(
+            "multipart_threshold is {}, but S3 does not accept single uploads larger than {}",
+            HumanBytes(threshold),
+            HumanBytes(MAX_PART_SIZE)
+        ));
+    }
+    Ok(threshold)
+}
+
+// Everything a part upload task needs to know about the multipart upload it belongs to
+struct MultipartTarget {
+    client: Client,
+    bucket: String,
+    key: String,
+    upload_id: String,
+    checksum_algorithm: Option<ChecksumAlgorithm>,
+    // Shared by all parts, so the limit holds for the whole upload
+    limiter: Option<Arc<RateLimiter>>,
+    // How often a part that failed is sent again
+    max_retries: u32,
+    // The SDK only makes one attempt per retry, see `retry::single_attempt_config`
+    sdk_retry: RetryConfig,
+    // Every part of an SSE-C upload needs the key
+    sse: ServerSideEncryption,
+    // What the upload expects at the key when it is completed
+    condition: WriteCondition,
+}
+
+// Where the bytes of a single part come from
+enum PartBody {
+    // A slice of a local file, read from disk only when the part is sent
+    File { path: PathBuf, offset: u64 },
+    // Bytes that were already read from a stream
+    Memory(Bytes),
+}
+
+struct Part {
+    part_number: i32,
+    length: u64,
+    body: PartBody,
+}
+
+impl Part {
+    async fn byte_stream(&self) -> Result<ByteStream, anyhow::Error> {
+        match &self.body {
+            PartBody::File { path, offset } => ByteStream::read_from()
+                .path(path)
+                .offset(*offset)
+                .length(Length::Exact(self.length))
+                .build()
+                .await
+                .map_err(|error| {
+                    anyhow::anyhow!(
+                        "Failed to read multipart upload part {} at byte {}: {}",
+                        self.part_number,
+                        offset,
+                        error
+                    )
+                }),
+            PartBody::Memory(bytes) => Ok(ByteStream::from(bytes.clone())),
+        }
+    }
+
+    // Computed from the local data before the part is sent, so S3 can check what it received
+    async fn checksum(&self, algorithm: ChecksumAlgorithm) -> Result<Checksum, anyhow::Error> {
+        let part_number = self.part_number;
+        let length = self.length;
+        match &self.body {
+            PartBody::File { path, offset } => {
+                let path = path.clone();
+                let offset = *offset;
+                tokio::task::spawn_blocking(move || {
+                    checksum::checksum_file(algorithm, &path, offset, length)
+                })
+                .await?
+                .map_err(|error| {
+                    anyhow::anyhow!(
+                        "Failed to read multipart upload part {} at byte {}: {}",
+                        part_number,
+                        offset,
+                        error
+                    )
+                })
+            }
+            PartBody::Memory(bytes) => {
+                let bytes = bytes.clone();
+                Ok(
+                    tokio::task::spawn_blocking(move || {
+                        checksum::checksum_bytes(algorithm, &bytes)
+                    })
+                    .await?,
+                )
+            }
+        }
+    }
+}
+
+fn completed_part(part_number: i32, e_tag: &str, checksum: Option<&Checksum>) -> CompletedPart {
+    CompletedPart::builder()
+        .part_number(part_number)
+        .e_tag(e_tag)
+        .set_checksum_crc32_c(checksum.and_then(Checksum::crc32c))
+        .set_checksum_crc64_nvme(checksum.and_then(Checksum::crc64nvme))
+        .set_checksum_sha256(checksum.and_then(Checksum::sha256))
+        .build()
+}
+
+async fn upload_part(
+    target: Arc<MultipartTarget>,
+    part: Part,
+    bar: ProgressBar,
+) -> Result<CompletedPart, anyhow::Error> {
+    log::trace!(
+        "Uploading part {} ({} bytes)",
+        &part.part_number,
+        &part.length
+    );
+    let checksum = match target.checksum_algorithm {
+        Some(algorithm) => Some(part.checksum(algorithm).await?),
+        None => None,
+    };
+    let mut retries = 0;
+    let upload_part_res = loop {
+        // The body is used up by every attempt, so each one reads the part again
+        let stream = part.byte_stream().await?;
+        let limiter = target.limiter.clone();
+
+        let result = target
+            .client
+            .upload_part()
+            .bucket(&target.bucket)
+            .key(&target.key)
+            .upload_id(&target.upload_id)
+            .part_number(part.part_number)
+            .set_checksum_algorithm(target.checksum_algorithm.map(|a| a.sdk_algorithm()))
+            .set_checksum_crc32_c(checksum.as_ref().and_then(Checksum::crc32c))
+            .set_checksum_crc64_nvme(checksum.as_ref().and_then(Checksum::crc64nvme))
+            .set_checksum_sha256(checksum.as_ref().and_then(Checksum::sha256))
+            .set_sse_customer_algorithm(target.sse.customer_algorithm())
+            .set_sse_customer_key(target.sse.customer_key())
+            .set_sse_customer_key_md5(target.sse.customer_key_md5())
+            .body(stream)
+            .customize()
+            .config_override(aws_sdk_s3::Config::builder().retry_config(target.sdk_retry.clone()))
+            .map_request(move |request| throttle::limit_request(request, limiter.clone()))
+            .send()
+            .await
+            .map_err(|error| {
+                crate::s3_error::S3OperationError::from_sdk_error(
+                    "UploadPart",
+                    &target.client,
+                    &target.bucket,
+                    Some(&target.key),
+                    &error,
+                )
+            });
+
+        match result {
+            Ok(output) => break output,
+            Err(error) if error.is_retryable() && retries < target.max_retries => {
+                retries += 1;
+                let delay = retry::backoff(retries);
+                log::debug!("Part {} failed: {}", &part.part_number, &error);
+                bar.println(format!(
+                    "{} | Part {} failed, retrying in {:.1}s ({} of {})",
+                    "NOTE".yellow(),
+                    part.part_number,
+                    delay.as_secs_f64(),
+                    retries,
+                    target.max_retries
+                ));
+                tokio::time::sleep(delay).await;
+            }
+            Err(error) if retries > 0 => {
+                return Err(anyhow::anyhow!(
+                    "Part {} failed after {} retries: {}",
+                    part.part_number,
+                    retries,
+                    error
+                ))
+            }
+            Err(error) => return Err(error.into()),
+        }
+    };
+
+    let e_tag = upload_part_res.e_tag().ok_or_else(|| {
+        anyhow::anyhow!(
+            "S3 UploadPart returned no ETag for part {}",
+            part.part_number
+        )
+    })?;
+    log::trace!("Part {} uploaded with ETag {}", &part.part_number, &e_tag);
+    bar.inc(part.length);
+
+    Ok(completed_part(part.part_number, e_tag, checksum.as_ref()))
+}
+
+// Sends CompleteMultipartUpload. Parts finish in whatever order S3 responds, but S3 needs them
+// sorted by part number.
+// With checksums turned on, the checksum of the whole object is worked out from the parts (which
+// is why we need `part_length`), and has to match what S3 ends up with.
+async fn complete_upload(
+    target: &MultipartTarget,
+    mut completed_parts: Vec<CompletedPart>,
+    part_length: impl Fn(i32) -> u64,
+) -> Result<(), anyhow::Error> {
+    completed_parts.sort_by_key(|part| part.part_number());
+
+    let expected_checksum = match target.checksum_algorithm {
+        Some(algorithm) => {
+            let parts = completed_parts
+                .iter()
+                .map(|part| {
+                    let part_number = part.part_number().unwrap_or_default();
+                    checksum::part_checksum(part)
+                        .map(|value| (value, part_length(part_number)))
+                        .ok_or_else(|| anyhow::anyhow!("Part {} has no checksum", part_number))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Some(checksum::multipart_checksum(algorithm, &parts)?)
+        }
+        None => None,
+    };
+    // S3 only accepts a checksum of the whole object for full object checksums
+    let full_object_checksum = expected_checksum
+        .as_ref()
+        .filter(|checksum| checksum.algorithm.multipart_type() == ChecksumType::FullObject);
+
+    let expected_e_tag = multipart_e_tag(&completed_parts);
+    let completed_multipart_upload = CompletedMultipartUpload::builder()
+        .set_parts(Some(completed_parts))
+        .build();
+
+    log::trace!("Sending complete_multipart_upload API call to S3 ");
+    let out = target
+        .client
+        .complete_multipart_upload()
+        .bucket(&target.bucket)
+        .key(&target.key)
+        .multipart_upload(completed_multipart_upload)
+        .upload_id(&target.upload_id)
+        .set_checksum_type(full_object_checksum.map(|_| ChecksumType::FullObject))
+        .set_checksum_crc32_c(full_object_checksum.and_then(Checksum::crc32c))
+        .set_checksum_crc64_nvme(full_object_checksum.and_then(Checksum::crc64nvme))
+        .set_sse_customer_algorithm(target.sse.customer_algorithm())
+        .set_sse_customer_key(target.sse.customer_key())
+        .set_sse_customer_key_md5(target.sse.customer_key_md5())
+        .set_if_none_match(target.condition.if_none_match())
+        .set_if_match(target.condition.if_match())
+        .send()
+        .await;
+    let out = match out {
+        Ok(out) => out,
+        Err(error) => {
+            precondition::check_write_error(
+                crate::s3_error::S3OperationError::from_sdk_error(
+                    "CompleteMultipartUpload",
+                    &target.client,
+                    &target.bucket,
+                    Some(&target.key),
+                    &error,
+                ),
+                &target.bucket,
+                &target.key,
+                &target.condition,
+                has_e_tag(target, expected_e_tag.as_deref()),
+            )
+            .await?;
+            // The attempt that completed it got the checksum, its response is gone
+            return Ok(());
+        }
+    };
+    log::debug!("CompleteMultipartUploadOutput: {:?}", out);
+
+    if let Some(expected_checksum) = expected_checksum {
+        expected_checksum.verify(
+            &target.key,
+            out.checksum_crc32_c(),
+            out.checksum_crc64_nvme(),
+            out.checksum_sha256(),
+        )?;
+    }
+    Ok(())
+}
+
+// S3 gives a multipart object the MD5 of the binary MD5s of its parts, followed by the number of
+// parts. `None` if a part has an ETag that is not an MD5.
+fn multipart_e_tag(parts: &[CompletedPart]) -> Option<String> {
+    let mut digests = Vec::with_capacity(parts.len() * 16);
+    for part in parts {
+        let e_tag = part.e_tag()?.trim_matches('"');
+        if e_tag.len() != 32 {
+            return None;
+        }
+        for offset in (0..e_tag.len()).step_by(2) {
+            digests.push(u8::from_str_radix(e_tag.get(offset..offset + 2)?, 16).ok()?);
+        }
+    }
+    Some(format!("\"{:x}-{}\"", md5::compute(&digests), parts.len()))
+}
+
+// Whether the object at the key of `target` is the one this upload completed
+async fn has_e_tag(target: &MultipartTarget, e_tag: Option<&str>) -> bool {
+    let Some(e_tag) = e_tag else {
+        return false;
+    };
+    match file_management::get_file_metadata(
+        &target.client,
+        &target.bucket,
+        &target.key,
+        &target.sse,
+    )
+    .await
+    {
+        Ok(Some(head)) => head.e_tag() == Some(e_tag),
+        _ => false,
+    }
+}
+
+// Waits for the next part upload to finish. If any part fails, all the parts that are still in
+// flight are cancelled before the error is returned, so nothing keeps uploading in the background.
+async fn join_next_part(
+    in_flight: &mut JoinSet<Result<CompletedPart, anyhow::Error>>,
+) -> Result<Option<CompletedPart>, anyhow::Error> {
+    let result = match in_flight.join_next().await {
+        None => return Ok(None),
+        Some(Ok(result)) => result,
+        Some(Err(join_error)) => Err(anyhow::anyhow!(
+            "Multipart upload part task failed: {}",
+            join_error
+        )),
+    };
+
+    match result {
+        Ok(completed_part) => {
+            log::trace!(
+                "Part {:?} finished, {} parts still in flight",
+                completed_part.part_number(),
+                in_flight.len()
+            );
+            Ok(Some(completed_part))
+        }
+        Err(error) => {
+            log::warn!(
+                "A part upload failed, cancelling {} outstanding parts",
+                in_flight.len()
+            );
+            in_flight.shutdown().await;
+            Err(error)
+        }
+    }
+}
+
+// Picks up where an earlier, interrupted upload of the same file left off. Only parts that both the
+// journal and S3 agree on are kept, everything else gets uploaded again.
+async fn resume_upload(
+    client: &Client,
+    key: &str,
+    file_name: &Path,
+    identity: &FileIdentity,
+    part_size: u64,
+    settings: &str,
+    shuk_config: &utils::Config,
+) -> Result<Option<UploadJournal>, anyhow::Error> {
+    let bucket = &shuk_config.bucket_name;
+    let checksum_algorithm = shuk_config.checksum_algorithm;
+    let sse = sse::settings(shuk_config);
+    let Some(mut journal) = UploadJournal::load(bucket, key, file_name)? else {
+        log::trace!("No upload journal found for {:?}", &file_name);
+        return Ok(None);
+    };
+
+    if !journal.matches(
+        identity,
+        part_size,
+        checksum_algorithm,
+        sse.fingerprint().as_deref(),
+        Some(settings),
+    ) {
+        log::trace!(
+            "Journal identity {:?} and settings {:?} do not match the local file {:?} and {}",
+            &journal.identity,
+            &journal.settings,
+            &identity,
+            settings
+        );
+        println!(
+            "{} | {:?} or its upload settings changed since its last interrupted upload, starting over",
+            "NOTE".yellow(),
+            &file_name
+        );
+        abort_stale_upload(client, &journal).await;
+        journal.remove()?;
+        return Ok(None);
+    }
+
+    let remote_parts = match client
+        .list_parts()
+        .bucket(bucket)
+        .key(key)
+        .upload_id(&journal.upload_id)
+        .set_sse_customer_algorithm(sse.customer_algorithm())
+        .set_sse_customer_key(sse.customer_key())
+        .set_sse_customer_key_md5(sse.customer_key_md5())
+        .into_paginator()
+        .items()
+        .send()
+        .collect::<Result<Vec<_>, _>>()
+        .await
+    {
+        Ok(parts) => parts,
+        Err(error) if error.code() == Some("NoSuchUpload") => {
+            println!(
+                "{} | The interrupted upload of {:?} no longer exists in S3, starting over",
+                "NOTE".yellow(),
+                &file_name
+            );
+            journal.remove()?;
+            return Ok(None);
+        }
+        Err(error) => {
+            return Err(crate::s3_error::S3OperationError::from_sdk_error(
+                "ListParts",
+                client,
+                bucket,
+                Some(key),
+                &error,
+            )
+            .into())
+        }
+    };
+    log::trace!("S3 reports {} uploaded parts", remote_parts.len());
+
+    let file_size = identity.file_size;
+    journal.parts.retain(|part| {
+        let offset = (part.part_number as u64 - 1) * part_size;
+        let expected_size = std::cmp::min(part_size, file_size.saturating_sub(offset));
+        let confirmed = remote_parts.iter().any(|remote| {
+            remote.part_number() == Some(part.part_number)
+                && remote.e_tag() == Some(part.e_tag.as_str())
+                && remote.size() == Some(expected_size as i64)
+        });
+        if !confirmed {
+            log::debug!(
+                "Part {} from the journal does not match S3, it will be uploaded again",
+                part.part_number
+            );
+        }
+        confirmed
+    });
+    journal.save()?;
+
+    Ok(Some(journal))
+}
+
+// Cleanup of a multipart upload we are not going to continue
+async fn abort_stale_upload(client: &Client, journal: &UploadJournal) {
+    log::trace!("Aborting stale multipart upload {}", &journal.upload_id);
+    cleanup::abort_upload(client, &journal.bucket, &journal.key, &journal.upload_id).await;
+}
+
+fn record_completed_part(journal: &mut UploadJournal, part: Option<CompletedPart>) {
+    let Some(part) = part else { return };
+    let (Some(part_number), Some(e_tag)) = (part.part_number(), part.e_tag()) else {
+        return;
+    };
+    journal.record_part(part_number, e_tag, checksum::part_checksum(&part));
+    // A journal we can not write only costs us the ability to resume, it is no reason to stop
+    // the upload itself
+    if let Err(error) = journal.save() {
+        log::warn!(
+            "Unable to update upload journal {:?}: {}",
+            journal.path(),
+            error
+        );
+    }
+}
+
+async fn multipart_upload(
+    client: &Client,
+    file_name: &Path,
+    pref_key: &str,
+    tags: &file_management::ObjectTags,
+    attributes: &ObjectAttributes,
+    metadata: &std::fs::Metadata,
+    shuk_config: &utils::Config,
+) -> Result<(), anyhow::Error> {
+    let file_size = metadata.len();
+    let bucket = &shuk_config.bucket_name;
+    let part_size = calculate_part_size(file_size, shuk_config.min_part_size)?;
+    let identity = FileIdentity::new(metadata, &tags.start_hash, &tags.end_hash)?;
+    let checksum_algorithm = shuk_config.checksum_algorithm;
+    let sse = sse::settings(shuk_config);
+    let settings = object_settings_fingerprint(tags, attributes, shuk_config);
+    let reproducible = key_is_reproducible(shuk_config, file_name, pref_key);
+
+    // Only an upload with a journal on disk can be resumed, any other is aborted when it fails
+    let (mut journal, resumable) = match resume_upload(
+        client,
+        pref_key,
+        file_name,
+        &identity,
+        part_size,
+        &settings,
+        shuk_config,
+    )
+    .await?
+    {
+        Some(journal) => {
+            println!(
+                "⏯️  | Resuming earlier upload, {} parts already in S3",
+                journal.parts.len()
+            );
+            (journal, reproducible)
+        }
+        None => {
+            let multipart_upload_res: CreateMultipartUploadOutput =
+                create_multipart_request(client, pref_key, Some(tags), attributes, shuk_config)
+                    .send()
+                    .await
+                    .map_err(|error| {
+                        crate::s3_error::S3OperationError::from_sdk_error(
+                            "CreateMultipartUpload",
+                            client,
+                            bucket,
+                            Some(pref_key),
+                            &error,
+                        )
+                    })?;
+
+            let upload_id = multipart_upload_res
+                .upload_id()
+                .ok_or_else(|| anyhow::anyhow!("S3 CreateMultipartUpload returned no upload ID"))?;
+            log::trace!(
+                "Generated the upload_id for multi-part uploads: {}",
+                &upload_id
+            );
+
+            let mut journal = UploadJournal::new(
+                bucket,
+                pref_key,
+                upload_id,
+                part_size,
+                file_name,
+                identity,
+                checksum_algorithm,
+            )?;
+            journal.encryption = sse.fingerprint();
+            journal.settings = Some(settings);
+            let saved = match journal.save() {
+                Ok(()) => true,
+                Err(error) => {
+                    log::warn!(
+                            "Unable to write upload journal {:?}, this upload will not be resumable: {}",
+                            journal.path(),
+                            error
+                        );
+                    false
+                }
+            };
+            (journal, saved && reproducible)
+        }
+    };
+    let registration = cleanup::register(client, bucket, pref_key, &journal.upload_id, resumable);
+
+    if let Err(error) = upload_parts_and_complete(
+        client,
+        file_name,
+        &mut journal,
+        file_size,
+        &attributes.condition,
+        shuk_config,
+    )
+    .await
+    {
+        if keep_for_resume(&error, resumable) {
+            println!(
+                "{} | The upload was interrupted. Run shuk again with the same file to resume it.",
+                "NOTE".yellow()
+            );
+            log::debug!("Upload journal kept at {:?}", journal.path());
+        } else {
+            registration.abort().await;
+            journal.remove()?;
+        }
+        return Err(error);
+    }
+
+    journal.remove()?;
+    Ok(())
+}
+
+// The journal is found by the key, so an upload is only resumable if the next run ends up at the
+// same one. A random key is only reused once it is recorded.
+fn key_is_reproducible(shuk_config: &utils::Config, file_name: &Path, key: &str) -> bool {
+    key_template::is_reproducible(shuk_config)
+        && unguessable::is_recorded(shuk_config, file_name, key)
+}
+
+// Whether a failed upload is kept so the next run can resume it. Completing an upload whose write
+// condition failed would only fail the same way again, so its parts are aborted instead of billed.
+fn keep_for_resume(error: &anyhow::Error, resumable: bool) -> bool {
+    resumable && !precondition::is_failed(error)
+}
+
+async fn upload_parts_and_complete(
+    client: &Client,
+    file_name: &Path,
+    journal: &mut UploadJournal,
+    file_size: u64,
+    condition: &WriteCondition,
+    shuk_config: &utils::Config,
+) -> Result<(), anyhow::Error> {
+    // A new bar is created here as we cannot use the same approach as with non multi-part
+    // uploads
+    let bar = ProgressBar::new(file_size);
+    bar.set_style(ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({eta})")
+        .unwrap()
+        .with_key("eta", |state: &ProgressState, w: &mut dyn Write| write!(w,"{:.1}s", state.eta().as_secs_f64()).unwrap())
+        .progress_chars("#>-"));
+
+    let concurrency = shuk_config
+        .upload_concurrency
+        .unwrap_or(DEFAULT_UPLOAD_CONCURRENCY)
+        .max(1);
+    log::trace!("Uploading up to {} parts in parallel", &concurrency);
+
+    let target = Arc::new(MultipartTarget {
+        client: client.clone(),
+        bucket: journal.bucket.clone(),
+        key: journal.key.clone(),
+        upload_id: journal.upload_id.clone(),
+        checksum_algorithm: journal.checksum_algorithm,
+        limiter: throttle::limiter(shuk_config),
+        max_retries: retry::max_retries(shuk_config),
+        sdk_retry: retry::single_attempt_config(shuk_config),
+        sse: sse::settings(shuk_config),
+        condition: condition.clone(),
+    });
+
+    let mut in_flight: JoinSet<Result<CompletedPart, anyhow::Error>> = JoinSet::new();
+    let mut part_number = 1;
+    let mut file_position: u64 = 0;
+
+    // main loop for uploading file chunks
+    log::trace!("Main loop for uploading file chunks starting...");
+    while file_position < file_size {
+        log::trace!("File Position: {}", &file_position);
+        let bytes_remaining = file_size - file_position;
+        log::trace!("Bytes Remaining: {}", &bytes_remaining);
+        let part_size = std::cmp::min(bytes_remaining, journal.part_size);
+        log::trace!("Size of part: {}", &part_size);
+        log::trace!("Part number : {}", &part_number);
+
+        if journal
+            .parts
+            .iter()
+            .any(|part| part.part_number == part_number)
+        {
+            log::trace!("Part {} is already uploaded, skipping", &part_number);
+            bar.inc(part_size);
+        } else {
+            // Wait for a free slot before scheduling the next part
+            if in_flight.len() >= concurrency {
+                let completed_part = join_next_part(&mut in_flight).await?;
+                record_completed_part(journal, completed_part);
+            }
+
+            in_flight.spawn(upload_part(
+                Arc::clone(&target),
+                Part {
+                    part_number,
+                    length: part_size,
+                    body: PartBody::File {
+                        path: file_name.to_path_buf(),
+                        offset: file_position,
+                    },
+                },
+                bar.clone(),
+            ));
+        }
+
+        file_position += part_size;
+        part_number += 1;
+    }
+    while !in_flight.is_empty() {
+        let completed_part = join_next_part(&mut in_flight).await?;
+        record_completed_part(journal, completed_part);
+    }
+    bar.finish();
+    log::trace!("Completed chunk uploads");
+
+    let completed_parts = journal
+        .parts
+        .iter()
+        .map(|part| {
+            let checksum = journal
+                .checksum_algorithm
+                .zip(part.checksum.clone())
+                .map(|(algorithm, value)| Checksum { algorithm, value });
+            completed_part(part.part_number, &part.e_tag, checksum.as_ref())
+        })
+        .collect();
+    let part_size = journal.part_size;
+    complete_upload(&target, completed_parts, |part_number| {
+        let offset = (part_number as u64 - 1) * part_size;
+        std::cmp::min(part_size, file_size.saturating_sub(offset))
+    })
+    .await?;
+
+    Ok(())
+}
+
 // FIX: Function has too many arguments
+// `key` is the full object key, with the bucket prefix, and `remote` what is there already
 pub async fn upload_object(
     client: &Client,
-    file_name: &PathBuf,
+    file_name: &Path,
     key: &str,
     tags: file_management::ObjectTags,
-    just_presign: bool,
+    remote: &Remote,
     upload_only: bool,
     shuk_config: &utils::Config,
 ) -> Result<Option<String>, anyhow::Error> {
@@ -150,7 +942,7 @@ pub async fn upload_object(
     );
 
     log::trace!("Opening {:?}", &file_name);
-    let mut file = match File::open(file_name) {
+    let file = match File::open(file_name) {
         Ok(file) => file,
         Err(e) => {
             return Err(anyhow::anyhow!("Failed to open file: {}", e));
@@ -167,34 +959,25 @@ pub async fn upload_object(
     let file_size = metadata.len();
     log::trace!("{:?} size: {:?}", &file_name, &file_size);
 
-    let pref_key = format!(
-        "{}{}",
-        &shuk_config.bucket_prefix.clone().unwrap_or("".into()),
-        key
-    );
-    log::trace!("Full Prefix key: {}", &pref_key);
-
-    // We should only presign the file
-    if just_presign {
-        if upload_only {
-            // just_presign + upload_only: should not occur in practice (handled in main),
-            // but for safety return Ok(None) with appropriate messaging
-            log::trace!("just_presign and upload_only both true — skipping presign.");
-            println!("========================================");
-            println!("✅ | File uploaded: {}, to S3 Bucket: {}", key, &shuk_config.bucket_name);
-            println!("✅ | No presigned URL generated (upload-only mode)");
-            println!("========================================");
-            return Ok(None);
-        }
+    log::trace!("Object key: {}", key);
+    let local_name = file_name
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let name = disposition::download_name(shuk_config, key, &local_name);
 
+    // The object is the same file, it only needs a new presigned URL. With `upload_only`,
+    // `share_file` has already stopped before getting here.
+    if *remote == Remote::Identical {
         log::trace!("The file needs to only be presigned.");
 
         let presigned_url = file_management::presign_file(
             client,
             &shuk_config.bucket_name,
             key,
-            shuk_config.bucket_prefix.clone(),
             shuk_config.presigned_time,
+            disposition::for_presign(shuk_config, name),
+            &sse::settings(shuk_config),
         )
         .await?;
         println!("========================================");
@@ -205,152 +988,54 @@ pub async fn upload_object(
     }
 
     log::trace!("The file needs to be uploaded.");
+    let head = file_management::read_head(file_name)
+        .map_err(|e| anyhow::anyhow!("Failed to read {:?}: {}", file_name, e))?;
+    let mut attributes = ObjectAttributes {
+        content_type: Some(file_management::content_type(shuk_config, name, &head)),
+        content_disposition: disposition::for_object(shuk_config, name),
+        metadata: shuk_config.metadata.clone().into_iter().collect(),
+        ..Default::default()
+    };
+    attributes
+        .metadata
+        .extend(content_addressed::name_metadata(shuk_config, name));
+    attributes.condition = WriteCondition::for_remote(remote);
+    log::trace!("Attributes of {:?}: {:?}", &file_name, &attributes);
     // Actually upload the file
-    // We need to do multi-part upload if file is larger than 4GB
-    if file_size > 4294967296 {
+    // We need to do multi-part upload if file is larger than the threshold (4GB by default)
+    let threshold = multipart_threshold(shuk_config)?;
+    if file_size > threshold {
         log::trace!(
-            "The file is bigger than 4294967296. Size: {}. Using multi-part upload.",
+            "The file is bigger than {}. Size: {}. Using multi-part upload.",
+            &threshold,
             &file_size
         );
 
         println!("========================================");
-        println!("💾 | File size is bigger than 4GB");
+        println!("💾 | File size is bigger than {}", HumanBytes(threshold));
         println!("💾 | Using multi-part upload");
         println!(
             "🚀 | Uploading file: {}, to S3 Bucket: {} | 🚀",
             key, &shuk_config.bucket_name
         );
         println!("========================================");
+        warn_about_composite_checksum(shuk_config);
 
-        // A new bar is created here as we cannot use the same approach as with non multi-part
-        // uploads
-        let bar = ProgressBar::new(file_size);
-        bar.set_style(ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({eta})")
-            .unwrap()
-            .with_key("eta", |state: &ProgressState, w: &mut dyn Write| write!(w,"{:.1}s", state.eta().as_secs_f64()).unwrap())
-            .progress_chars("#>-"));
-
-        let multipart_upload_res: CreateMultipartUploadOutput = client
-            .create_multipart_upload()
-            .bucket(&shuk_config.bucket_name)
-            .key(&pref_key)
-            .set_tagging(Some(tags.to_string()))
-            .send()
-            .await
-            .map_err(|error| {
-                crate::s3_error::S3OperationError::from_sdk_error(
-                    "CreateMultipartUpload",
-                    client,
-                    &shuk_config.bucket_name,
-                    Some(&pref_key),
-                    &error,
-                )
-            })?;
-
-        let upload_id = multipart_upload_res
-            .upload_id()
-            .ok_or_else(|| anyhow::anyhow!("S3 CreateMultipartUpload returned no upload ID"))?;
-        log::trace!(
-            "Generated the upload_id for multi-part uploads: {}",
-            &upload_id
-        );
-
-        let mut completed_parts = Vec::new();
-        let mut part_number = 1;
-        let mut file_position: u64 = 0;
-
-        // main loop for uploading file chunks
-        log::trace!("Main loop for uploading file chunks starting...");
-        while file_position < file_size {
-            log::trace!("File Position: {}", &file_position);
-            let bytes_remaining = file_size - file_position;
-            log::trace!("Bytes Remaining: {}", &bytes_remaining);
-            let part_size = std::cmp::min(bytes_remaining, PART_SIZE);
-            log::trace!("Size of part: {}", &part_size);
-            log::trace!("Part number : {}", &part_number);
-
-            let mut part_data = vec![0; part_size as usize];
-            if let Err(e) = file.read_exact(&mut part_data) {
-                return Err(anyhow::anyhow!("Failed to read file: {}", e));
-            }
-
-            let stream = ByteStream::read_from()
-                .path(file_name)
-                .offset(file_position)
-                .length(Length::Exact(part_size))
-                .build()
-                .await
-                .map_err(|error| {
-                    anyhow::anyhow!(
-                        "Failed to read multipart upload part {} at byte {}: {}",
-                        part_number,
-                        file_position,
-                        error
-                    )
-                })?;
-
-            bar.set_position(file_position);
-
-            let upload_part_res = client
-                .upload_part()
-                .bucket(&shuk_config.bucket_name)
-                .key(&pref_key)
-                .upload_id(upload_id)
-                .part_number(part_number)
-                .body(stream)
-                .send()
-                .await
-                .map_err(|error| {
-                    crate::s3_error::S3OperationError::from_sdk_error(
-                        "UploadPart",
-                        client,
-                        &shuk_config.bucket_name,
-                        Some(&pref_key),
-                        &error,
-                    )
-                })?;
-
-            let e_tag = upload_part_res.e_tag().ok_or_else(|| {
-                anyhow::anyhow!("S3 UploadPart returned no ETag for part {}", part_number)
-            })?;
-            let completed_part = CompletedPart::builder()
-                .part_number(part_number)
-                .e_tag(e_tag)
-                .build();
-
-            completed_parts.push(completed_part);
-
-            file_position += part_size;
-            part_number += 1;
-        }
-        log::trace!("Completed chunk uploads");
-
-        let completed_multipart_upload = CompletedMultipartUpload::builder()
-            .set_parts(Some(completed_parts))
-            .build();
-
-        log::trace!("Sending complete_multipart_upload API call to S3 ");
-        client
-            .complete_multipart_upload()
-            .bucket(&shuk_config.bucket_name)
-            .key(&pref_key)
-            .multipart_upload(completed_multipart_upload)
-            .upload_id(upload_id)
-            .send()
-            .await
-            .map_err(|error| {
-                crate::s3_error::S3OperationError::from_sdk_error(
-                    "CompleteMultipartUpload",
-                    client,
-                    &shuk_config.bucket_name,
-                    Some(&pref_key),
-                    &error,
-                )
-            })?;
+        multipart_upload(
+            client,
+            file_name,
+            key,
+            &tags,
+            &attributes,
+            &metadata,
+            shuk_config,
+        )
+        .await?;
     } else {
-        // There is no need for multi-part uploads, as the file is smaller than 4GB
+        // There is no need for multi-part uploads, as the file is smaller than the threshold
         log::trace!(
-            "The file is smaller than 4294967296. Size: {}. No need for multi-part upload.",
+            "The file is smaller than {}. Size: {}. No need for multi-part upload.",
+            &threshold,
             &file_size
         );
         println!("========================================");
@@ -371,35 +1056,99 @@ pub async fn upload_object(
             Err(e) => return Err(anyhow::anyhow!("Failed to create ByteStream: {}", e)),
         };
 
+        let checksum = match shuk_config.checksum_algorithm {
+            Some(algorithm) => {
+                log::trace!(
+                    "Computing the {:?} checksum of {:?}",
+                    &algorithm,
+                    &file_name
+                );
+                let path = file_name.to_path_buf();
+                Some(
+                    tokio::task::spawn_blocking(move || {
+                        checksum::checksum_file(algorithm, &path, 0, file_size)
+                    })
+                    .await?
+                    .map_err(|e| anyhow::anyhow!("Failed to read {:?}: {}", file_name, e))?,
+                )
+            }
+            None => None,
+        };
+
         log::trace!("Sending put_object API call to S3");
-        let request = client
-            .put_object()
-            .bucket(&shuk_config.bucket_name)
-            .key(&pref_key)
-            .set_tagging(Some(tags.to_string()))
-            .body(body);
-
-        // for the progress bar
+        let request = put_object_request(
+            client,
+            key,
+            &tags,
+            &attributes,
+            checksum.as_ref(),
+            shuk_config,
+        )
+        .body(body);
+
+        // for the bandwidth limit and the progress bar
+        let limiter = throttle::limiter(shuk_config);
         let customized = request
             .customize()
+            .map_request(move |request| throttle::limit_request(request, limiter.clone()))
             .map_request(ProgressBody::<SdkBody>::replace);
-        let out = customized.send().await.map_err(|error| {
-            crate::s3_error::S3OperationError::from_sdk_error(
-                "PutObject",
-                client,
-                &shuk_config.bucket_name,
-                Some(&pref_key),
-                &error,
-            )
-        })?;
+        let out = match customized.send().await {
+            Ok(out) => Some(out),
+            Err(error) => {
+                precondition::check_write_error(
+                    crate::s3_error::S3OperationError::from_sdk_error(
+                        "PutObject",
+                        client,
+                        &shuk_config.bucket_name,
+                        Some(key),
+                        &error,
+                    ),
+                    &shuk_config.bucket_name,
+                    key,
+                    &attributes.condition,
+                    file_management::has_tags(client, &shuk_config.bucket_name, key, &tags),
+                )
+                .await?;
+                None
+            }
+        };
         log::debug!("PutObjectOutput: {:?}", out);
+        // Without a response there is no checksum to check, an earlier attempt got it
+        if let Some((checksum, out)) = checksum.zip(out) {
+            checksum.verify(
+                key,
+                out.checksum_crc32_c(),
+                out.checksum_crc64_nvme(),
+                out.checksum_sha256(),
+            )?;
+        }
     }
 
-    // After upload completes, handle upload-only vs normal mode
+    finish_upload(
+        client,
+        key,
+        disposition::for_presign(shuk_config, name),
+        upload_only,
+        shuk_config,
+    )
+    .await
+}
+
+// After upload completes, handle upload-only vs normal mode
+async fn finish_upload(
+    client: &Client,
+    key: &str,
+    content_disposition: Option<String>,
+    upload_only: bool,
+    shuk_config: &utils::Config,
+) -> Result<Option<String>, anyhow::Error> {
     if upload_only {
         log::trace!("Upload-only mode: skipping presign_file call.");
         println!("========================================");
-        println!("✅ | File uploaded: {}, to S3 Bucket: {}", key, &shuk_config.bucket_name);
+        println!(
+            "✅ | File uploaded: {}, to S3 Bucket: {}",
+            key, &shuk_config.bucket_name
+        );
         println!("✅ | No presigned URL generated (upload-only mode)");
         println!("========================================");
         return Ok(None);
@@ -414,8 +1163,9 @@ pub async fn upload_object(
         client,
         &shuk_config.bucket_name,
         key,
-        shuk_config.bucket_prefix.clone(),
         shuk_config.presigned_time,
+        content_disposition,
+        &sse::settings(shuk_config),
     )
     .await?;
     println!("========================================");
@@ -424,3 +1174,578 @@ pub async fn upload_object(
 
     Ok(Some(presigned_url))
 }
+
+fn warn_about_composite_checksum(shuk_config: &utils::Config) {
+    if let Some(warning) = shuk_config
+        .checksum_algorithm
+        .and_then(|algorithm| algorithm.multipart_warning())
+    {
+        println!("{} | {}", "NOTE".yellow(), warning);
+    }
+}
+
+// Part sizes for uploads where we do not know the length up front. We start at `min_part_size` and
+// double the part size every 1,000 parts, so 10,000 parts of 5MB and up can hold close to the
+// 5TB S3 limit, while small streams still use small parts.
+pub fn stream_part_size(part_number: i32, min_part_size: u64) -> u64 {
+    let doublings = ((part_number.max(1) - 1) / 1000) as u32;
+    let factor = 1u64.checked_shl(doublings).unwrap_or(u64::MAX);
+    std::cmp::min(min_part_size.saturating_mul(factor), MAX_PART_SIZE)
+}
+
+// Reads until `size` bytes are in the buffer or the stream ends
+fn read_chunk<R: Read>(reader: &mut R, size: u64) -> Result<Vec<u8>, anyhow::Error> {
+    let mut buffer = Vec::with_capacity(size as usize);
+    tokio::task::block_in_place(|| reader.by_ref().take(size).read_to_end(&mut buffer))
+        .map_err(|error| anyhow::anyhow!("Failed to read the input stream: {}", error))?;
+    Ok(buffer)
+}
+
+// Headers and metadata that are set on the object when it is created
+#[derive(Debug, Default, Clone)]
+pub struct ObjectAttributes {
+    pub content_type: Option<String>,
+    pub content_encoding: Option<String>,
+    pub content_disposition: Option<String>,
+    pub metadata: HashMap<String, String>,
+    // What has to be at the key for the object to be written, see `precondition`
+    pub condition: WriteCondition,
+}
+
+// PutObject with everything shuk sets on a new object: the tags and attributes, the storage class,
+// server-side encryption, Object Lock, the checksum of the body and the write condition
+fn put_object_request(
+    client: &Client,
+    key: &str,
+    tags: &file_management::ObjectTags,
+    attributes: &ObjectAttributes,
+    checksum: Option<&Checksum>,
+    shuk_config: &utils::Config,
+) -> PutObjectFluentBuilder {
+    let sse = sse::settings(shuk_config);
+    let lock = object_lock::settings(shuk_config);
+    client
+        .put_object()
+        .bucket(&shuk_config.bucket_name)
+        .key(key)
+        .tagging(tags.to_string())
+        .set_content_type(attributes.content_type.clone())
+        .set_content_encoding(attributes.content_encoding.clone())
+        .set_content_disposition(attributes.content_disposition.clone())
+        .set_metadata(Some(attributes.metadata.clone()))
+        .set_storage_class(shuk_config.storage_class.map(|c| c.sdk_storage_class()))
+        .set_server_side_encryption(sse.algorithm.clone())
+        .set_ssekms_key_id(sse.kms_key_id.clone())
+        .set_bucket_key_enabled(sse.bucket_key_enabled)
+        .set_sse_customer_algorithm(sse.customer_algorithm())
+        .set_sse_customer_key(sse.customer_key())
+        .set_sse_customer_key_md5(sse.customer_key_md5())
+        .set_object_lock_mode(lock.mode)
+        .set_object_lock_retain_until_date(lock.retain_until)
+        .set_object_lock_legal_hold_status(lock.legal_hold)
+        .set_checksum_algorithm(checksum.map(|c| c.algorithm.sdk_algorithm()))
+        .set_checksum_crc32_c(checksum.and_then(Checksum::crc32c))
+        .set_checksum_crc64_nvme(checksum.and_then(Checksum::crc64nvme))
+        .set_checksum_sha256(checksum.and_then(Checksum::sha256))
+        .set_if_none_match(attributes.condition.if_none_match())
+        .set_if_match(attributes.condition.if_match())
+}
+
+// What `create_multipart_request` puts on the object, as a hash for the upload journal. A resumed
+// upload completes with the settings it was created with, so it is only resumed if they are still
+// the same. A retention given as an age is compared as written, not as the date it works out to.
+fn object_settings_fingerprint(
+    tags: &file_management::ObjectTags,
+    attributes: &ObjectAttributes,
+    shuk_config: &utils::Config,
+) -> String {
+    let metadata: std::collections::BTreeMap<_, _> = attributes.metadata.iter().collect();
+    let settings = format!(
+        "{}\n{:?}\n{:?}\n{:?}\n{:?}\n{:?}\n{:?}\n{:?}\n{:?}",
+        tags,
+        attributes.content_type,
+        attributes.content_encoding,
+        attributes.content_disposition,
+        metadata,
+        shuk_config.storage_class,
+        shuk_config.object_lock_mode,
+        shuk_config.object_lock_retain_until,
+        shuk_config.object_lock_legal_hold,
+    );
+    format!("{:x}", md5::compute(settings))
+}
+
+// CreateMultipartUpload with the same object settings as `put_object_request`. The checksums go
+// with every part, and the write condition with CompleteMultipartUpload.
+fn create_multipart_request(
+    client: &Client,
+    key: &str,
+    tags: Option<&file_management::ObjectTags>,
+    attributes: &ObjectAttributes,
+    shuk_config: &utils::Config,
+) -> CreateMultipartUploadFluentBuilder {
+    let sse = sse::settings(shuk_config);
+    let lock = object_lock::settings(shuk_config);
+    let checksum_algorithm = shuk_config.checksum_algorithm;
+    client
+        .create_multipart_upload()
+        .bucket(&shuk_config.bucket_name)
+        .key(key)
+        .set_tagging(tags.map(ToString::to_string))
+        .set_content_type(attributes.content_type.clone())
+        .set_content_encoding(attributes.content_encoding.clone())
+        .set_content_disposition(attributes.content_disposition.clone())
+        .set_metadata(Some(attributes.metadata.clone()))
+        .set_storage_class(shuk_config.storage_class.map(|c| c.sdk_storage_class()))
+        .set_server_side_encryption(sse.algorithm.clone())
+        .set_ssekms_key_id(sse.kms_key_id.clone())
+        .set_bucket_key_enabled(sse.bucket_key_enabled)
+        .set_sse_customer_algorithm(sse.customer_algorithm())
+        .set_sse_customer_key(sse.customer_key())
+        .set_sse_customer_key_md5(sse.customer_key_md5())
+        .set_object_lock_mode(lock.mode)
+        .set_object_lock_retain_until_date(lock.retain_until)
+        .set_object_lock_legal_hold_status(lock.legal_hold)
+        .set_checksum_algorithm(checksum_algorithm.map(|a| a.sdk_algorithm()))
+        .set_checksum_type(checksum_algorithm.map(|a| a.multipart_type()))
+}
+
+// Data for `upload_stream`. The reader may be compressed or encrypted on the way, so the hasher of
+// the original data is passed in separately, together with the attributes for the object. `name`
+// is what the download is called.
+pub struct StreamSource<R> {
+    pub reader: R,
+    pub hasher: Arc<Mutex<file_management::PartialHasher>>,
+    pub attributes: ObjectAttributes,
+    pub name: String,
+}
+
+impl<R> StreamSource<R> {
+    // The hashes can only be taken once everything was read
+    fn object_tags(
+        &self,
+        shuk_config: &utils::Config,
+    ) -> Result<file_management::ObjectTags, anyhow::Error> {
+        let partial_hash = std::mem::take(
+            &mut *self
+                .hasher
+                .lock()
+                .map_err(|_| anyhow::anyhow!("The partial hasher is poisoned"))?,
+        )
+        .finish();
+        Ok(file_management::ObjectTags::new(
+            partial_hash.start_hash,
+            partial_hash.end_hash,
+            shuk_config,
+        ))
+    }
+}
+
+// Uploads data of unknown length, such as stdin. Small inputs go up with a single PutObject.
+// Anything bigger than one part is buffered into parts as it arrives and sent as a multipart
+// upload, with up to `upload_concurrency` parts held in memory at a time.
+// The partial hashes can only be known once the whole stream was read, so the object is tagged
+// after the upload finishes.
+pub async fn upload_stream<R: Read>(
+    client: &Client,
+    mut source: StreamSource<R>,
+    key: &str,
+    upload_only: bool,
+    shuk_config: &utils::Config,
+) -> Result<Option<String>, anyhow::Error> {
+    let bucket = &shuk_config.bucket_name;
+    log::trace!("Start of streaming upload to {}/{}", &bucket, key);
+
+    // This also makes sure the configured min_part_size is something S3 will accept
+    let min_part_size = calculate_part_size(0, shuk_config.min_part_size)?;
+
+    println!("========================================");
+    println!(
+        "🚀 | Uploading stream: {}, to S3 Bucket: {} | 🚀",
+        key, &bucket
+    );
+    println!("========================================");
+
+    let first_part = read_chunk(&mut source.reader, stream_part_size(1, min_part_size))?;
+
+    if (first_part.len() as u64) < min_part_size {
+        log::trace!(
+            "The stream ended after {} bytes. No need for multi-part upload.",
+            first_part.len()
+        );
+        let tags = source.object_tags(shuk_config)?;
+        let checksum = shuk_config
+            .checksum_algorithm
+            .map(|algorithm| checksum::checksum_bytes(algorithm, &first_part));
+
+        log::trace!("Sending put_object API call to S3");
+        let request = put_object_request(
+            client,
+            key,
+            &tags,
+            &source.attributes,
+            checksum.as_ref(),
+            shuk_config,
+        )
+        .body(ByteStream::from(first_part));
+
+        // for the bandwidth limit and the progress bar
+        let limiter = throttle::limiter(shuk_config);
+        let customized = request
+            .customize()
+            .map_request(move |request| throttle::limit_request(request, limiter.clone()))
+            .map_request(ProgressBody::<SdkBody>::replace);
+        let out = match customized.send().await {
+            Ok(out) => Some(out),
+            Err(error) => {
+                precondition::check_write_error(
+                    crate::s3_error::S3OperationError::from_sdk_error(
+                        "PutObject",
+                        client,
+                        bucket,
+                        Some(key),
+                        &error,
+                    ),
+                    bucket,
+                    key,
+                    &source.attributes.condition,
+                    file_management::has_tags(client, bucket, key, &tags),
+                )
+                .await?;
+                None
+            }
+        };
+        log::debug!("PutObjectOutput: {:?}", out);
+        if let Some((checksum, out)) = checksum.zip(out) {
+            checksum.verify(
+                key,
+                out.checksum_crc32_c(),
+                out.checksum_crc64_nvme(),
+                out.checksum_sha256(),
+            )?;
+        }
+    } else {
+        log::trace!("The stream is bigger than one part. Using multi-part upload.");
+        warn_about_composite_checksum(shuk_config);
+        stream_multipart_upload(client, &mut source, first_part, key, shuk_config).await?;
+
+        let tags = source.object_tags(shuk_config)?;
+        log::trace!("Tagging the uploaded stream with: {:#?}", &tags);
+        client
+            .put_object_tagging()
+            .bucket(bucket)
+            .key(key)
+            .tagging(tags.to_tagging()?)
+            .send()
+            .await
+            .map_err(|error| {
+                crate::s3_error::S3OperationError::from_sdk_error(
+                    "PutObjectTagging",
+                    client,
+                    bucket,
+                    Some(key),
+                    &error,
+                )
+            })?;
+    }
+
+    finish_upload(
+        client,
+        key,
+        disposition::for_presign(shuk_config, &source.name),
+        upload_only,
+        shuk_config,
+    )
+    .await
+}
+
+async fn stream_multipart_upload<R: Read>(
+    client: &Client,
+    source: &mut StreamSource<R>,
+    first_part: Vec<u8>,
+    pref_key: &str,
+    shuk_config: &utils::Config,
+) -> Result<(), anyhow::Error> {
+    let bucket = &shuk_config.bucket_name;
+    let min_part_size = calculate_part_size(0, shuk_config.min_part_size)?;
+
+    // The tags are only known once the whole stream was read, they are put on afterwards
+    let multipart_upload_res: CreateMultipartUploadOutput =
+        create_multipart_request(client, pref_key, None, &source.attributes, shuk_config)
+            .send()
+            .await
+            .map_err(|error| {
+                crate::s3_error::S3OperationError::from_sdk_error(
+                    "CreateMultipartUpload",
+                    client,
+                    bucket,
+                    Some(pref_key),
+                    &error,
+                )
+            })?;
+    let upload_id = multipart_upload_res
+        .upload_id()
+        .ok_or_else(|| anyhow::anyhow!("S3 CreateMultipartUpload returned no upload ID"))?;
+    log::trace!(
+        "Generated the upload_id for multi-part uploads: {}",
+        &upload_id
+    );
+
+    let target = Arc::new(MultipartTarget {
+        client: client.clone(),
+        bucket: bucket.clone(),
+        key: pref_key.to_string(),
+        upload_id: upload_id.to_string(),
+        checksum_algorithm: shuk_config.checksum_algorithm,
+        limiter: throttle::limiter(shuk_config),
+        max_retries: retry::max_retries(shuk_config),
+        sdk_retry: retry::single_attempt_config(shuk_config),
+        sse: sse::settings(shuk_config),
+        condition: source.attributes.condition.clone(),
+    });
+
+    // A stream can not be resumed, so an upload that fails is cleaned up right away
+    let registration = cleanup::register(client, bucket, pref_key, upload_id, false);
+    let result =
+        stream_parts_and_complete(&target, source, first_part, min_part_size, shuk_config).await;
+    if result.is_err() {
+        registration.abort().await;
+    }
+    result
+}
+
+async fn stream_parts_and_complete<R: Read>(
+    target: &Arc<MultipartTarget>,
+    source: &mut StreamSource<R>,
+    first_part: Vec<u8>,
+    min_part_size: u64,
+    shuk_config: &utils::Config,
+) -> Result<(), anyhow::Error> {
+    // We do not know how much is coming, so there is no bar to fill, just a running total
+    let bar = ProgressBar::new_spinner();
+    bar.set_style(
+        ProgressStyle::with_template(
+            "{spinner:.green} [{elapsed_precise}] {bytes} uploaded ({bytes_per_sec})",
+        )
+        .unwrap(),
+    );
+
+    let concurrency = shuk_config
+        .upload_concurrency
+        .unwrap_or(DEFAULT_UPLOAD_CONCURRENCY)
+        .max(1);
+    let mut in_flight: JoinSet<Result<CompletedPart, anyhow::Error>> = JoinSet::new();
+    let mut completed_parts = Vec::new();
+    // Stream parts grow as the upload goes on, so their lengths are needed for the checksum
+    let mut part_lengths = Vec::new();
+    let mut part_number: i32 = 1;
+    let mut stream_size: u64 = 0;
+    let mut chunk = first_part;
+
+    log::trace!("Main loop for uploading stream chunks starting...");
+    while !chunk.is_empty() {
+        if part_number as u64 > MAX_PARTS {
+            in_flight.shutdown().await;
+            return Err(anyhow::anyhow!(
+                "The stream is larger than S3 allows in {} parts",
+                MAX_PARTS
+            ));
+        }
+
+        // Wait for a free slot before scheduling the next part
+        if in_flight.len() >= concurrency {
+            completed_parts.extend(join_next_part(&mut in_flight).await?);
+        }
+
+        log::trace!("Part number : {} ({} bytes)", &part_number, chunk.len());
+        stream_size += chunk.len() as u64;
+        part_lengths.push(chunk.len() as u64);
+        in_flight.spawn(upload_part(
+            Arc::clone(target),
+            Part {
+                part_number,
+                length: chunk.len() as u64,
+                body: PartBody::Memory(Bytes::from(chunk)),
+            },
+            bar.clone(),
+        ));
+
+        part_number += 1;
+        chunk = match read_chunk(
+            &mut source.reader,
+            stream_part_size(part_number, min_part_size),
+        ) {
+            Ok(chunk) => chunk,
+            Err(error) => {
+                in_flight.shutdown().await;
+                return Err(error);
+            }
+        };
+    }
+    while !in_flight.is_empty() {
+        completed_parts.extend(join_next_part(&mut in_flight).await?);
+    }
+    bar.finish();
+    log::trace!(
+        "Completed chunk uploads, {} bytes in {} parts",
+        stream_size,
+        completed_parts.len()
+    );
+
+    complete_upload(target, completed_parts, |part_number| {
+        part_lengths[part_number as usize - 1]
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MB: u64 = 1024 * 1024;
+    const GB: u64 = 1024 * MB;
+
+    #[test]
+    fn small_files_use_the_minimum_part_size() {
+        assert_eq!(calculate_part_size(5 * GB, None).unwrap(), MIN_PART_SIZE);
+        assert_eq!(calculate_part_size(5 * GB, Some(16 * MB)).unwrap(), 16 * MB);
+    }
+
+    #[test]
+    fn large_files_grow_the_part_size_to_fit_the_part_limit() {
+        // 5MB parts stop working at roughly 48.8GB
+        let file_size = 100 * GB;
+        let part_size = calculate_part_size(file_size, None).unwrap();
+        assert!(part_size > MIN_PART_SIZE);
+        assert_eq!(
+            part_size % MB,
+            0,
+            "part size should be a whole number of MB"
+        );
+        assert!(file_size.div_ceil(part_size) <= MAX_PARTS);
+    }
+
+    #[test]
+    fn largest_possible_object_fits() {
+        let part_size = calculate_part_size(MAX_OBJECT_SIZE, None).unwrap();
+        assert!(part_size <= MAX_PART_SIZE);
+        assert!(MAX_OBJECT_SIZE.div_ceil(part_size) <= MAX_PARTS);
+    }
+
+    #[test]
+    fn stream_parts_grow_to_fit_the_largest_object() {
+        assert_eq!(stream_part_size(1, MIN_PART_SIZE), MIN_PART_SIZE);
+        assert_eq!(stream_part_size(1000, MIN_PART_SIZE), MIN_PART_SIZE);
+        assert_eq!(stream_part_size(1001, MIN_PART_SIZE), 2 * MIN_PART_SIZE);
+
+        let capacity: u64 = (1..=MAX_PARTS as i32)
+            .map(|part_number| stream_part_size(part_number, MIN_PART_SIZE))
+            .sum();
+        assert!(capacity > 4 * 1024 * GB);
+        assert_eq!(stream_part_size(i32::MAX, MIN_PART_SIZE), MAX_PART_SIZE);
+    }
+
+    #[test]
+    fn object_settings_change_the_fingerprint() {
+        let fingerprint = |extra: &str, metadata: &[(&str, &str)]| {
+            let shuk_config = utils::Config::from_toml(extra);
+            let tags = file_management::ObjectTags::new("a".into(), "b".into(), &shuk_config);
+            let attributes = ObjectAttributes {
+                content_type: Some("video/mp4".into()),
+                metadata: metadata
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect(),
+                ..Default::default()
+            };
+            object_settings_fingerprint(&tags, &attributes, &shuk_config)
+        };
+        let plain = fingerprint("", &[]);
+        assert_eq!(fingerprint("", &[]), plain);
+        assert_ne!(fingerprint("[tags]\nteam = \"data\"", &[]), plain);
+        assert_ne!(fingerprint("", &[("project", "shuk")]), plain);
+        assert_ne!(fingerprint("storage_class = \"GLACIER_IR\"", &[]), plain);
+        assert_ne!(fingerprint("object_lock_legal_hold = true", &[]), plain);
+    }
+
+    #[test]
+    fn uploads_that_lost_their_write_condition_are_not_resumed() {
+        use aws_sdk_s3::error::{ErrorMetadata, SdkError};
+        use aws_sdk_s3::operation::complete_multipart_upload::CompleteMultipartUploadError;
+        use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+
+        let client = Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .behavior_version_latest()
+                .region(aws_sdk_s3::config::Region::new("us-east-1"))
+                .build(),
+        );
+        let failure = |code: &str, status: u16| {
+            let error = SdkError::service_error(
+                CompleteMultipartUploadError::generic(ErrorMetadata::builder().code(code).build()),
+                HttpResponse::try_from(
+                    http::Response::builder()
+                        .status(status)
+                        .body(SdkBody::empty())
+                        .unwrap(),
+                )
+                .unwrap(),
+            );
+            precondition::write_error(
+                crate::s3_error::S3OperationError::from_sdk_error(
+                    "CompleteMultipartUpload",
+                    &client,
+                    "foo",
+                    Some("shuk/video.mp4"),
+                    &error,
+                ),
+                "foo",
+                "shuk/video.mp4",
+                &WriteCondition::Create,
+            )
+        };
+
+        assert!(keep_for_resume(&failure("SlowDown", 503), true));
+        assert!(!keep_for_resume(&failure("SlowDown", 503), false));
+        assert!(!keep_for_resume(&failure("PreconditionFailed", 412), true));
+        assert!(!keep_for_resume(
+            &failure("ConditionalRequestConflict", 409),
+            true
+        ));
+    }
+
+    #[test]
+    fn multipart_e_tags_are_worked_out_from_the_parts() {
+        let parts = [
+            completed_part(1, "\"0cc175b9c0f1b6a831c399e269772661\"", None),
+            completed_part(2, "\"92eb5ffee6ae2fec3ad71c777531578f\"", None),
+        ];
+        assert_eq!(
+            multipart_e_tag(&parts).as_deref(),
+            Some("\"96e024ba2074fe77e8e965ba43a704be-2\"")
+        );
+        assert_eq!(multipart_e_tag(&[completed_part(1, "\"abc\"", None)]), None);
+    }
+
+    #[test]
+    fn unguessable_uploads_without_a_saved_key_are_aborted() {
+        let file = std::env::temp_dir().join(format!("shuk-unrecorded-{}", std::process::id()));
+        std::fs::write(&file, b"data").unwrap();
+        let key = "shuk/0f3a9c";
+        let unguessable = utils::Config::from_toml("unguessable_keys = true");
+        let resumable = key_is_reproducible(&unguessable, &file, key);
+        let plain = key_is_reproducible(&utils::Config::from_toml(""), &file, key);
+        std::fs::remove_file(&file).unwrap();
+
+        assert!(!resumable);
+        assert!(!keep_for_resume(&anyhow::anyhow!("interrupted"), resumable));
+        assert!(plain);
+    }
+
+    #[test]
+    fn impossible_part_sizes_are_rejected() {
+        assert!(calculate_part_size(5 * GB, Some(MB)).is_err());
+        assert!(calculate_part_size(5 * GB, Some(6 * GB)).is_err());
+        assert!(calculate_part_size(MAX_OBJECT_SIZE + 1, None).is_err());
+    }
+}
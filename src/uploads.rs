@@ -0,0 +1,344 @@
+// `shuk uploads`: finding and aborting unfinished multipart uploads
+use std::io::{self, Write};
+use std::time::{Duration, SystemTime};
+
+use aws_sdk_s3::Client;
+
+use crate::sse::{self, ServerSideEncryption};
+use crate::{cleanup, s3_error::S3OperationError, share, utils};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultipartUpload {
+    pub key: String,
+    pub upload_id: String,
+    // How long ago the upload was started, if S3 said when that was
+    pub age: Option<Duration>,
+    // Only counted for the uploads that were selected, see `count_parts`
+    pub parts: usize,
+    pub size: u64,
+}
+
+// Which uploads `shuk uploads` works on. Without keys and without an age every upload is selected.
+#[derive(Debug, Default)]
+pub struct Selection {
+    // Object keys (with or without the bucket prefix) or upload IDs
+    pub keys: Vec<String>,
+    pub older_than: Option<Duration>,
+}
+
+impl Selection {
+    pub fn matches(&self, upload: &MultipartUpload, prefix: &str) -> bool {
+        let key_matches = self.keys.is_empty()
+            || self.keys.iter().any(|key| {
+                *key == upload.key
+                    || *key == upload.upload_id
+                    || format!("{}{}", prefix, key) == upload.key
+            });
+        // An upload without a start time can not be shown to be old enough, so it is left alone
+        let age_matches = match self.older_than {
+            None => true,
+            Some(older_than) => upload.age.is_some_and(|age| age >= older_than),
+        };
+        key_matches && age_matches
+    }
+}
+
+// Runs `shuk uploads`. Without `abort` the selected uploads are only listed.
+pub async fn manage_uploads(
+    client: &mut Client,
+    sdk_config: &aws_config::SdkConfig,
+    shuk_config: &utils::Config,
+    selection: &Selection,
+    abort: bool,
+    assume_yes: bool,
+) -> Result<(), anyhow::Error> {
+    let bucket = &shuk_config.bucket_name;
+    let prefix = shuk_config.bucket_prefix.as_deref().unwrap_or("");
+
+    let uploads = match list_uploads(client, bucket, prefix).await {
+        Ok(uploads) => uploads,
+        Err(error) => {
+            let Some(bucket_region) = error.retry_region().map(str::to_string) else {
+                return Err(error.into());
+            };
+            share::switch_region(client, sdk_config, bucket, &error, bucket_region);
+            list_uploads(client, bucket, prefix).await?
+        }
+    };
+    let total = uploads.len();
+    let mut selected: Vec<MultipartUpload> = uploads
+        .into_iter()
+        .filter(|upload| selection.matches(upload, prefix))
+        .collect();
+    // One ListParts per upload, so only for the ones that are shown
+    let sse = sse::settings(shuk_config);
+    for upload in &mut selected {
+        (upload.parts, upload.size) =
+            count_parts(client, bucket, &upload.key, &upload.upload_id, &sse).await?;
+    }
+
+    println!("========================================");
+    if selected.is_empty() {
+        println!(
+            "✨ | No unfinished multipart uploads{} in s3://{}/{}",
+            if total > 0 { " match" } else { "" },
+            bucket,
+            prefix
+        );
+        return Ok(());
+    }
+    println!(
+        "🧾 | {} unfinished multipart upload{} in s3://{}/{}",
+        selected.len(),
+        if selected.len() == 1 { "" } else { "s" },
+        bucket,
+        prefix
+    );
+    print!("{}", format_uploads(&selected));
+    println!("========================================");
+
+    if !abort {
+        println!("Pass --abort to delete their parts");
+        return Ok(());
+    }
+    if !assume_yes && !confirm(selected.len())? {
+        println!("Nothing was aborted");
+        return Ok(());
+    }
+
+    let mut failed = 0;
+    for upload in &selected {
+        if !cleanup::abort_upload(client, bucket, &upload.key, &upload.upload_id).await {
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        return Err(anyhow::anyhow!(
+            "{} of {} uploads could not be aborted",
+            failed,
+            selected.len()
+        ));
+    }
+    Ok(())
+}
+
+fn confirm(count: usize) -> Result<bool, anyhow::Error> {
+    print!(
+        "ABORT {} UPLOAD{} AND DELETE THEIR PARTS? Y/N: ",
+        count,
+        if count == 1 { "" } else { "S" }
+    );
+    io::stdout().flush()?; // so the answer is typed on the same line as above
+    let mut confirmation = String::new();
+    io::stdin().read_line(&mut confirmation)?;
+    Ok(confirmation.trim().eq_ignore_ascii_case("y"))
+}
+
+// Every unfinished multipart upload under `prefix`. Their parts are not counted yet.
+pub async fn list_uploads(
+    client: &Client,
+    bucket: &str,
+    prefix: &str,
+) -> Result<Vec<MultipartUpload>, S3OperationError> {
+    let now = SystemTime::now();
+    let mut uploads = Vec::new();
+    let mut key_marker: Option<String> = None;
+    let mut upload_id_marker: Option<String> = None;
+    loop {
+        log::trace!("Listing multipart uploads after {:?}", &key_marker);
+        let output = client
+            .list_multipart_uploads()
+            .bucket(bucket)
+            .prefix(prefix)
+            .set_key_marker(key_marker.take())
+            .set_upload_id_marker(upload_id_marker.take())
+            .send()
+            .await
+            .map_err(|error| {
+                S3OperationError::from_sdk_error(
+                    "ListMultipartUploads",
+                    client,
+                    bucket,
+                    None,
+                    &error,
+                )
+            })?;
+
+        for upload in output.uploads() {
+            let (Some(key), Some(upload_id)) = (upload.key(), upload.upload_id()) else {
+                continue;
+            };
+            let age = upload
+                .initiated()
+                .and_then(|initiated| SystemTime::try_from(*initiated).ok())
+                .and_then(|initiated| now.duration_since(initiated).ok());
+            uploads.push(MultipartUpload {
+                key: key.to_string(),
+                upload_id: upload_id.to_string(),
+                age,
+                parts: 0,
+                size: 0,
+            });
+        }
+
+        if !output.is_truncated().unwrap_or(false) {
+            break;
+        }
+        key_marker = output.next_key_marker().map(str::to_string);
+        upload_id_marker = output.next_upload_id_marker().map(str::to_string);
+        if key_marker.is_none() {
+            break;
+        }
+    }
+    Ok(uploads)
+}
+
+// The number of parts an upload has and how many bytes they take up. The parts of an SSE-C upload
+// can only be listed with its key.
+async fn count_parts(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+    sse: &ServerSideEncryption,
+) -> Result<(usize, u64), S3OperationError> {
+    let mut parts = 0;
+    let mut size = 0;
+    let mut part_number_marker: Option<String> = None;
+    loop {
+        let output = client
+            .list_parts()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .set_sse_customer_algorithm(sse.customer_algorithm())
+            .set_sse_customer_key(sse.customer_key())
+            .set_sse_customer_key_md5(sse.customer_key_md5())
+            .set_part_number_marker(part_number_marker.take())
+            .send()
+            .await
+            .map_err(|error| {
+                S3OperationError::from_sdk_error("ListParts", client, bucket, Some(key), &error)
+            })?;
+        for part in output.parts() {
+            parts += 1;
+            size += part.size().unwrap_or_default().max(0) as u64;
+        }
+
+        if !output.is_truncated().unwrap_or(false) {
+            break;
+        }
+        part_number_marker = output.next_part_number_marker().map(str::to_string);
+        if part_number_marker.is_none() {
+            break;
+        }
+    }
+    Ok((parts, size))
+}
+
+// A short age like `3d 4h` or `12m`, only the two largest units
+pub fn format_age(age: Duration) -> String {
+    let seconds = age.as_secs();
+    let (days, hours, minutes) = (seconds / 86400, seconds / 3600 % 24, seconds / 60 % 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m", minutes)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+pub fn format_uploads(uploads: &[MultipartUpload]) -> String {
+    let key_width = uploads
+        .iter()
+        .map(|upload| upload.key.chars().count())
+        .chain(["KEY".len()])
+        .max()
+        .unwrap_or_default();
+
+    let mut table = format!(
+        "{:<key_width$}  {:<7}  {:>5}  {:>10}  UPLOAD ID\n",
+        "KEY", "AGE", "PARTS", "SIZE"
+    );
+    for upload in uploads {
+        table.push_str(&format!(
+            "{:<key_width$}  {:<7}  {:>5}  {:>10}  {}\n",
+            upload.key,
+            upload
+                .age
+                .map(format_age)
+                .unwrap_or_else(|| "-".to_string()),
+            upload.parts,
+            indicatif::HumanBytes(upload.size).to_string(),
+            upload.upload_id
+        ));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upload(key: &str, upload_id: &str, age: Option<u64>) -> MultipartUpload {
+        MultipartUpload {
+            key: key.to_string(),
+            upload_id: upload_id.to_string(),
+            age: age.map(Duration::from_secs),
+            parts: 3,
+            size: 24 * 1024 * 1024,
+        }
+    }
+
+    #[test]
+    fn uploads_are_selected_by_key_upload_id_and_age() {
+        let old = upload("shuk/old.iso", "id-old", Some(8 * 86400));
+        let new = upload("shuk/new.iso", "id-new", Some(3600));
+        let unknown = upload("shuk/unknown.iso", "id-unknown", None);
+
+        let everything = Selection::default();
+        assert!(everything.matches(&old, "shuk/"));
+        assert!(everything.matches(&unknown, "shuk/"));
+
+        let by_key = Selection {
+            keys: vec!["old.iso".to_string(), "id-new".to_string()],
+            older_than: None,
+        };
+        assert!(by_key.matches(&old, "shuk/"));
+        assert!(by_key.matches(&new, "shuk/"));
+        assert!(!by_key.matches(&unknown, "shuk/"));
+
+        let by_age = Selection {
+            keys: Vec::new(),
+            older_than: Some(Duration::from_secs(7 * 86400)),
+        };
+        assert!(by_age.matches(&old, "shuk/"));
+        assert!(!by_age.matches(&new, "shuk/"));
+        assert!(!by_age.matches(&unknown, "shuk/"));
+    }
+
+    #[test]
+    fn ages_and_tables_are_formatted() {
+        assert_eq!(format_age(Duration::from_secs(42)), "42s");
+        assert_eq!(format_age(Duration::from_secs(12 * 60 + 5)), "12m");
+        assert_eq!(
+            format_age(Duration::from_secs(5 * 3600 + 12 * 60)),
+            "5h 12m"
+        );
+        assert_eq!(
+            format_age(Duration::from_secs(3 * 86400 + 4 * 3600)),
+            "3d 4h"
+        );
+
+        let table = format_uploads(&[upload("shuk/old.iso", "id-old", Some(8 * 86400))]);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("KEY"));
+        assert!(lines[1].starts_with("shuk/old.iso  8d 0h"));
+        assert!(lines[1].contains("24.00 MiB"));
+        assert!(lines[1].ends_with("id-old"));
+    }
+}
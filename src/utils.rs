@@ -1,20 +1,32 @@
 use aws_config::meta::region::RegionProviderChain;
+use aws_config::retry::RetryConfig;
 use aws_config::BehaviorVersion;
 use aws_types::region::Region;
 
+use std::collections::BTreeMap;
 use std::fs;
 use std::io;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::exit;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 use serde::Deserialize;
 use serde::Deserializer;
 use serde::Serialize;
 
+use crate::archive::ArchiveFormat;
+use crate::checksum::ChecksumAlgorithm;
+use crate::compression::Compression;
+use crate::conflict::OnConflict;
 use crate::constants;
+use crate::disposition::Disposition;
+use crate::object_lock::{self, LockMode};
+use crate::retry::RetryMode;
+use crate::sse::{CustomerKey, SseMode};
+use crate::storage_class::StorageClass;
+use crate::tagging;
 use colored::*;
 use dirs::home_dir;
 
@@ -45,8 +57,9 @@ pub fn setup_logging(verbose: bool) {
 pub async fn configure_aws(
     fallback_region: String,
     profile_name: Option<&String>,
+    retry_config: RetryConfig,
 ) -> aws_config::SdkConfig {
-    let mut loader = aws_config::defaults(BehaviorVersion::latest());
+    let mut loader = aws_config::defaults(BehaviorVersion::latest()).retry_config(retry_config);
 
     if let Some(profile) = profile_name.map(|s| s.as_str()) {
         loader = loader.profile_name(profile);
@@ -90,6 +103,49 @@ pub struct Config {
     pub aws_profile: Option<String>,
     pub use_clipboard: Option<bool>,
     pub fallback_region: Option<String>,
+    pub upload_concurrency: Option<usize>,
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub min_part_size: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub multipart_threshold: Option<u64>,
+    pub encrypt: Option<bool>,
+    pub encryption_recipients: Option<Vec<String>>,
+    pub identity_file: Option<PathBuf>,
+    pub compress: Option<Compression>,
+    pub checksum_algorithm: Option<ChecksumAlgorithm>,
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub max_bandwidth: Option<u64>,
+    pub max_retries: Option<u32>,
+    pub retry_mode: Option<RetryMode>,
+    pub content_disposition: Option<Disposition>,
+    pub storage_class: Option<StorageClass>,
+    pub server_side_encryption: Option<SseMode>,
+    pub sse_kms_key_id: Option<String>,
+    pub sse_bucket_key: Option<bool>,
+    pub sse_customer_key_file: Option<PathBuf>,
+    // Read from `sse_customer_key_file` by `sse::prepare`
+    #[serde(skip)]
+    pub sse_customer_key: Option<CustomerKey>,
+    pub object_lock_mode: Option<LockMode>,
+    // A date, a timestamp or an age, see `object_lock::parse_retain_until`
+    pub object_lock_retain_until: Option<String>,
+    pub object_lock_legal_hold: Option<bool>,
+    // Placeholders for the object keys, see `key_template`
+    pub key_template: Option<String>,
+    // Random object keys, see `unguessable`
+    pub unguessable_keys: Option<bool>,
+    // Keys from the SHA-256 of the file, see `content_addressed`
+    pub content_addressed: Option<bool>,
+    // What to do when a different file is at the key, see `conflict`
+    pub on_conflict: Option<OnConflict>,
+    // Only set with `--content-type`, every other upload gets the type of its own file
+    #[serde(skip)]
+    pub content_type: Option<String>,
+    // Tables have to come last in the file, so these stay at the end
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
 }
 
 // This function exists so we can append "/" to any prefix we read from the configuration file.
@@ -111,6 +167,80 @@ where
     }
 }
 
+// Sizes can be written as a plain number of bytes, or as a string like "64MB" or "1.5G"
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("`{}` is not a valid size", value))?;
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1024,
+        "M" | "MB" | "MIB" => 1024 * 1024,
+        "G" | "GB" | "GIB" => 1024 * 1024 * 1024,
+        "T" | "TB" | "TIB" => 1024 * 1024 * 1024 * 1024,
+        _ => return Err(format!("`{}` has an unknown size unit `{}`", value, unit)),
+    };
+    Ok((number * multiplier as f64) as u64)
+}
+
+// Ages such as "30m", "12h", "7d" or "2w"
+pub fn parse_age(value: &str) -> Result<std::time::Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("`{}` is not a valid age", value))?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        "" => return Err(format!("`{}` needs a unit: s, m, h, d or w", value)),
+        _ => return Err(format!("`{}` has an unknown age unit `{}`", value, unit)),
+    };
+    Ok(std::time::Duration::from_secs(
+        number.saturating_mul(multiplier),
+    ))
+}
+
+// A MIME type such as "application/pdf" or "text/plain; charset=utf-8"
+pub fn parse_content_type(value: &str) -> Result<String, String> {
+    value
+        .trim()
+        .parse::<mime_guess::mime::Mime>()
+        .map(|mime| mime.to_string())
+        .map_err(|_| format!("`{}` is not a valid content type", value))
+}
+
+fn deserialize_size<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(u64),
+        Text(String),
+    }
+
+    match Option::<Size>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Size::Bytes(bytes)) => Ok(Some(bytes)),
+        Some(Size::Text(text)) => parse_size(&text)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+    }
+}
+
 impl Config {
     pub fn load_config() -> Result<Self, anyhow::Error> {
         log::trace!("Parsing the configuration file");
@@ -139,6 +269,128 @@ impl Config {
             exit(1);
         }
     }
+
+    // A config with only the required settings, plus `extra`, for the tests
+    #[cfg(test)]
+    pub fn from_toml(extra: &str) -> Config {
+        toml::from_str(&format!(
+            "bucket_name = \"foo\"\nbucket_prefix = \"shuk\"\npresigned_time = 60\n{}",
+            extra
+        ))
+        .unwrap()
+    }
+
+    // Command line arguments take precedence over whatever is in the configuration file
+    pub fn apply_args(&mut self, args: &Args) {
+        if let Some(content_disposition) = args.content_disposition {
+            log::trace!(
+                "Overriding content_disposition with: {:?}",
+                content_disposition
+            );
+            self.content_disposition = Some(content_disposition);
+        }
+        if let Some(storage_class) = args.storage_class {
+            log::trace!("Overriding storage_class with: {:?}", storage_class);
+            self.storage_class = Some(storage_class);
+        }
+        if let Some(sse) = args.sse {
+            log::trace!("Overriding server_side_encryption with: {:?}", sse);
+            self.server_side_encryption = Some(sse);
+        }
+        if let Some(key_id) = &args.sse_kms_key_id {
+            log::trace!("Overriding sse_kms_key_id with: {}", key_id);
+            self.server_side_encryption = Some(SseMode::SseKms);
+            self.sse_kms_key_id = Some(key_id.clone());
+        }
+        if args.sse_bucket_key {
+            log::trace!("Turning on sse_bucket_key from the command line");
+            self.sse_bucket_key = Some(true);
+        }
+        if let Some(key_file) = &args.sse_customer_key_file {
+            log::trace!("Overriding sse_customer_key_file with: {:?}", key_file);
+            self.server_side_encryption = Some(SseMode::SseC);
+            self.sse_customer_key_file = Some(key_file.clone());
+        }
+        if let Some(content_type) = &args.content_type {
+            log::trace!("Overriding the Content-Type with: {}", content_type);
+            self.content_type = Some(content_type.clone());
+        }
+        if let Some(concurrency) = args.concurrency {
+            log::trace!("Overriding upload_concurrency with: {}", concurrency);
+            self.upload_concurrency = Some(concurrency);
+        }
+        if args.encrypt || args.passphrase || !args.recipients.is_empty() {
+            log::trace!("Encryption has been turned on from the command line");
+            self.encrypt = Some(true);
+        }
+        if !args.recipients.is_empty() {
+            log::trace!(
+                "Overriding encryption_recipients with: {:?}",
+                &args.recipients
+            );
+            self.encryption_recipients = Some(args.recipients.clone());
+        }
+        if args.passphrase {
+            log::trace!("Encrypting with a passphrase instead of the configured recipients");
+            self.encryption_recipients = None;
+        }
+        if let Some(compress) = args.compress {
+            log::trace!("Overriding compress with: {:?}", compress);
+            self.compress = Some(compress);
+        }
+        if let Some(checksum) = args.checksum {
+            log::trace!("Overriding checksum_algorithm with: {:?}", checksum);
+            self.checksum_algorithm = Some(checksum);
+        }
+        if let Some(limit_rate) = args.limit_rate {
+            log::trace!("Overriding max_bandwidth with: {}", limit_rate);
+            self.max_bandwidth = Some(limit_rate);
+        }
+        if let Some(retries) = args.retries {
+            log::trace!("Overriding max_retries with: {}", retries);
+            self.max_retries = Some(retries);
+        }
+        if let Some(retry_mode) = args.retry_mode {
+            log::trace!("Overriding retry_mode with: {:?}", retry_mode);
+            self.retry_mode = Some(retry_mode);
+        }
+        if let Some(mode) = args.object_lock_mode {
+            log::trace!("Overriding object_lock_mode with: {:?}", mode);
+            self.object_lock_mode = Some(mode);
+        }
+        if let Some(retain_until) = &args.retain_until {
+            log::trace!("Overriding object_lock_retain_until with: {}", retain_until);
+            self.object_lock_retain_until = Some(retain_until.clone());
+        }
+        if args.legal_hold {
+            log::trace!("Turning on object_lock_legal_hold from the command line");
+            self.object_lock_legal_hold = Some(true);
+        }
+        if let Some(key_template) = &args.key_template {
+            log::trace!("Overriding key_template with: {}", key_template);
+            self.key_template = Some(key_template.clone());
+        }
+        if args.unguessable {
+            log::trace!("Turning on unguessable_keys from the command line");
+            self.unguessable_keys = Some(true);
+        }
+        if args.content_addressed {
+            log::trace!("Turning on content_addressed from the command line");
+            self.content_addressed = Some(true);
+        }
+        if let Some(on_conflict) = args.on_conflict {
+            log::trace!("Overriding on_conflict with: {:?}", on_conflict);
+            self.on_conflict = Some(on_conflict);
+        }
+        for (key, value) in &args.tags {
+            log::trace!("Overriding the tag {} with: {}", key, value);
+            self.tags.insert(key.clone(), value.clone());
+        }
+        for (key, value) in &args.metadata {
+            log::trace!("Overriding the metadata {} with: {}", key, value);
+            self.metadata.insert(key.clone(), value.clone());
+        }
+    }
 }
 //======================================== END CONFIG PARSING
 //
@@ -287,7 +539,7 @@ pub fn set_into_clipboard(s: String) -> Result<(), Box<dyn std::error::Error>> {
             }
 
             log::debug!("Successfully copied to X11 clipboard");
-        },
+        }
         "macos" => {
             log::trace!("Detected macOS, attempting clipboard operation with pbcopy");
             let mut child = Command::new("pbcopy")
@@ -319,7 +571,7 @@ pub fn set_into_clipboard(s: String) -> Result<(), Box<dyn std::error::Error>> {
             }
 
             log::debug!("Successfully copied to macOS clipboard");
-        },
+        }
         "windows" => {
             log::trace!("Detected Windows, attempting clipboard operation with clip.exe");
             let mut child = Command::new("clip")
@@ -351,7 +603,7 @@ pub fn set_into_clipboard(s: String) -> Result<(), Box<dyn std::error::Error>> {
             }
 
             log::debug!("Successfully copied to Windows clipboard");
-        },
+        }
         os => {
             log::error!("Unsupported operating system: {}", os);
             return Err(format!("Unsupported operating system: {}", os).into());
@@ -364,16 +616,385 @@ pub fn set_into_clipboard(s: String) -> Result<(), Box<dyn std::error::Error>> {
 
 //======================================== ARGUMENT PARSING
 #[derive(Debug, Parser, Default)]
-#[command(version, about, long_about = None)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 pub struct Args {
-    #[arg(required_unless_present("init"))]
-    pub filename: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: Option<Commands>,
+    #[arg(
+        required_unless_present("init"),
+        value_name = "FILENAME",
+        help = "Files to upload, or `-` (followed by an object name) to read from stdin"
+    )]
+    pub filenames: Vec<PathBuf>,
     // the init flag. So we can copy the config files locally
-    #[arg(long, conflicts_with("filename"))]
+    #[arg(long, conflicts_with("filenames"))]
     pub init: bool,
     #[arg(short, long, help = "Enable verbose logging")]
     pub verbose: bool,
-    #[arg(long, help = "Upload file without generating a presigned URL", conflicts_with("init"))]
+    #[arg(
+        long,
+        help = "Upload file without generating a presigned URL",
+        conflicts_with("init")
+    )]
     pub upload_only: bool,
+    #[arg(
+        short,
+        long,
+        help = "Upload every file in the given directories, keeping their relative paths",
+        conflicts_with("init")
+    )]
+    pub recursive: bool,
+    #[arg(
+        short = 'j',
+        long,
+        help = "Number of multipart upload parts to send in parallel",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        conflicts_with("init")
+    )]
+    pub concurrency: Option<usize>,
+    #[arg(
+        long,
+        help = "Object name to use when reading from stdin",
+        conflicts_with("init")
+    )]
+    pub name: Option<String>,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        help = "Pack each directory into a single archive while uploading it",
+        conflicts_with_all(["init", "recursive", "name"])
+    )]
+    pub archive: Option<ArchiveFormat>,
+    #[arg(
+        long,
+        help = "Encrypt before uploading, to the configured recipients or with a passphrase",
+        conflicts_with("init")
+    )]
+    pub encrypt: bool,
+    #[arg(
+        long = "recipient",
+        value_name = "PUBLIC_KEY",
+        help = "Encrypt to this age or SSH public key (can be repeated)",
+        conflicts_with("init")
+    )]
+    pub recipients: Vec<String>,
+    #[arg(
+        long,
+        help = "Encrypt with a passphrase, even if recipients are configured",
+        conflicts_with_all(["init", "recipients"])
+    )]
+    pub passphrase: bool,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        help = "Compress while uploading and set Content-Encoding so browsers get the original file",
+        conflicts_with("init")
+    )]
+    pub compress: Option<Compression>,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "ALGORITHM",
+        help = "Have S3 verify the upload against a checksum of the local data",
+        conflicts_with("init")
+    )]
+    pub checksum: Option<ChecksumAlgorithm>,
+    #[arg(
+        long,
+        value_name = "RATE",
+        help = "Upload at most this many bytes per second, such as 500K or 2M",
+        value_parser = parse_size,
+        conflicts_with("init")
+    )]
+    pub limit_rate: Option<u64>,
+    #[arg(
+        long,
+        value_name = "COUNT",
+        help = "How often a failed request or multipart part is retried",
+        conflicts_with("init")
+    )]
+    pub retries: Option<u32>,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        help = "How the AWS SDK retries requests",
+        conflicts_with("init")
+    )]
+    pub retry_mode: Option<RetryMode>,
+    #[arg(
+        long,
+        value_name = "TYPE",
+        help = "Content-Type for the uploaded objects, instead of detecting it from each file",
+        value_parser = parse_content_type,
+        conflicts_with("init")
+    )]
+    pub content_type: Option<String>,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        help = "Name downloads after the shared file, and either force the download or show it in the browser",
+        conflicts_with("init")
+    )]
+    pub content_disposition: Option<Disposition>,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "CLASS",
+        ignore_case = true,
+        help = "S3 storage class for the uploaded objects",
+        conflicts_with("init")
+    )]
+    pub storage_class: Option<StorageClass>,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        help = "Have S3 encrypt the objects at rest",
+        conflicts_with("init")
+    )]
+    pub sse: Option<SseMode>,
+    #[arg(
+        long,
+        value_name = "KEY_ID",
+        help = "KMS key to encrypt with, implies --sse sse-kms",
+        conflicts_with_all(["init", "sse_customer_key_file"])
+    )]
+    pub sse_kms_key_id: Option<String>,
+    #[arg(
+        long,
+        help = "Use an S3 Bucket Key for SSE-KMS, which cuts down on KMS requests",
+        conflicts_with("init")
+    )]
+    pub sse_bucket_key: bool,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "File with the 256-bit key for SSE-C, implies --sse sse-c",
+        conflicts_with("init")
+    )]
+    pub sse_customer_key_file: Option<PathBuf>,
+    #[arg(
+        long = "tag",
+        value_name = "KEY=VALUE",
+        value_parser = tagging::parse_key_value,
+        help = "Tag the uploaded objects, on top of the configured tags (can be repeated)",
+        conflicts_with("init")
+    )]
+    pub tags: Vec<(String, String)>,
+    #[arg(
+        long = "meta",
+        value_name = "KEY=VALUE",
+        value_parser = tagging::parse_key_value,
+        help = "Store this x-amz-meta-* metadata with the uploaded objects (can be repeated)",
+        conflicts_with("init")
+    )]
+    pub metadata: Vec<(String, String)>,
+    #[arg(
+        long,
+        value_enum,
+        ignore_case = true,
+        value_name = "MODE",
+        help = "Object Lock retention mode for the uploaded objects, needs --retain-until",
+        conflicts_with("init")
+    )]
+    pub object_lock_mode: Option<LockMode>,
+    #[arg(
+        long,
+        value_name = "DATE",
+        value_parser = object_lock::parse_retain_until_arg,
+        help = "Keep the uploaded objects locked until this date (2027-03-31), or for this long (90d)",
+        conflicts_with("init")
+    )]
+    pub retain_until: Option<String>,
+    #[arg(
+        long,
+        help = "Put a legal hold on the uploaded objects, which locks them until it is removed",
+        conflicts_with("init")
+    )]
+    pub legal_hold: bool,
+    #[arg(
+        long = "key",
+        value_name = "TEMPLATE",
+        help = "Object key template, such as {date:%Y/%m/%d}/{user}/{stem}.{ext}",
+        conflicts_with("init")
+    )]
+    pub key_template: Option<String>,
+    #[arg(
+        long,
+        help = "Upload under a random key, and reuse it when the same file is shared again",
+        conflicts_with_all(["init", "key_template"])
+    )]
+    pub unguessable: bool,
+    #[arg(
+        long,
+        help = "Upload under the SHA-256 of the file, so files with the same content are stored once",
+        conflicts_with_all(["init", "key_template", "unguessable"])
+    )]
+    pub content_addressed: bool,
+    #[arg(
+        long,
+        value_name = "POLICY",
+        help = "What to do when a different file is already at the key",
+        conflicts_with("init")
+    )]
+    pub on_conflict: Option<OnConflict>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Commands {
+    #[command(about = "Decrypt a file that was encrypted by shuk")]
+    Decrypt {
+        #[arg(
+            value_name = "FILE",
+            help = "The encrypted file, or `-` to read it from stdin"
+        )]
+        input: PathBuf,
+        #[arg(
+            short,
+            long,
+            value_name = "FILE",
+            help = "Where to write the decrypted file, or `-` for stdout. Defaults to the input without `.age`"
+        )]
+        output: Option<PathBuf>,
+        #[arg(
+            short,
+            long,
+            value_name = "FILE",
+            help = "age identity file or SSH private key to decrypt with"
+        )]
+        identity: Option<PathBuf>,
+    },
+    #[command(about = "List unfinished multipart uploads under the bucket prefix, and abort them")]
+    Uploads {
+        #[arg(
+            value_name = "KEY",
+            help = "Only uploads to these keys (with or without the prefix), or with these upload IDs"
+        )]
+        keys: Vec<String>,
+        #[arg(
+            long,
+            value_name = "AGE",
+            help = "Only uploads started longer ago than this, such as 12h or 7d",
+            value_parser = parse_age
+        )]
+        older_than: Option<std::time::Duration>,
+        #[arg(long, help = "Abort the uploads and delete their parts")]
+        abort: bool,
+        #[arg(short, long, help = "Abort without asking first", requires("abort"))]
+        yes: bool,
+    },
+}
+
+impl Args {
+    // `-` as the first file name means the data comes from stdin
+    pub fn reads_stdin(&self) -> bool {
+        self.filenames.first().map(PathBuf::as_path) == Some(Path::new("-"))
+    }
+
+    // The name can be passed either as `shuk - name.txt` or as `shuk --name name.txt -`
+    pub fn stdin_object_name(&self) -> Option<&str> {
+        if !self.reads_stdin() {
+            return None;
+        }
+        self.name
+            .as_deref()
+            .or_else(|| self.filenames.get(1).and_then(|name| name.to_str()))
+    }
+
+    // Catches the file name combinations clap can not express on its own
+    pub fn check_sources(&self) -> Result<(), anyhow::Error> {
+        if self.reads_stdin() {
+            if self.archive.is_some() {
+                return Err(anyhow::anyhow!(
+                    "Only directories can be archived, not data from stdin"
+                ));
+            }
+            let names = self.filenames.len() - 1 + usize::from(self.name.is_some());
+            if names == 0 {
+                return Err(anyhow::anyhow!(
+                    "Reading from stdin needs a name for the object. Usage: shuk - <NAME> or shuk --name <NAME> -"
+                ));
+            }
+            if names > 1 {
+                return Err(anyhow::anyhow!(
+                    "Reading from stdin takes exactly one object name and can not be combined with other files"
+                ));
+            }
+        } else if self
+            .filenames
+            .iter()
+            .any(|path| path.as_path() == Path::new("-"))
+        {
+            return Err(anyhow::anyhow!(
+                "`-` (stdin) has to come first and can not be combined with other files"
+            ));
+        } else if self.name.is_some() {
+            return Err(anyhow::anyhow!(
+                "An object name can only be given when reading from stdin (`-`)"
+            ));
+        }
+        Ok(())
+    }
 }
 //=========================ALPHA=============== END ARGUMENT PARSING
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes_with_and_without_units() {
+        assert_eq!(parse_size("1024"), Ok(1024));
+        assert_eq!(parse_size("8M"), Ok(8 * 1024 * 1024));
+        assert_eq!(parse_size("64MB"), Ok(64 * 1024 * 1024));
+        assert_eq!(parse_size("1.5GiB"), Ok(1536 * 1024 * 1024));
+        assert_eq!(parse_size(" 2 k "), Ok(2048));
+        assert!(parse_size("fast").is_err());
+        assert!(parse_size("10 parsecs").is_err());
+    }
+
+    #[test]
+    fn ages_are_parsed() {
+        assert_eq!(
+            parse_age("30m"),
+            Ok(std::time::Duration::from_secs(30 * 60))
+        );
+        assert_eq!(
+            parse_age("7d"),
+            Ok(std::time::Duration::from_secs(7 * 86400))
+        );
+        assert_eq!(
+            parse_age(" 2W "),
+            Ok(std::time::Duration::from_secs(14 * 86400))
+        );
+        assert!(parse_age("7").is_err());
+        assert!(parse_age("7 fortnights").is_err());
+        assert!(parse_age("d").is_err());
+    }
+
+    #[test]
+    fn config_sizes_accept_numbers_and_strings() {
+        let config: Config = toml::from_str(
+            r#"
+bucket_name = "foo"
+bucket_prefix = ""
+presigned_time = 60
+min_part_size = "16MB"
+multipart_threshold = 1073741824
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.min_part_size, Some(16 * 1024 * 1024));
+        assert_eq!(config.multipart_threshold, Some(1024 * 1024 * 1024));
+    }
+}
@@ -2,6 +2,14 @@ use clap::Parser;
 use shuk::utils::Args;
 use std::path::PathBuf;
 
+// Parses a config holding the required settings plus `extra`
+fn config_from_toml(extra: &str) -> Result<shuk::utils::Config, toml::de::Error> {
+    toml::from_str(&format!(
+        "bucket_name = \"foo\"\nbucket_prefix = \"shuk\"\npresigned_time = 60\n{}",
+        extra
+    ))
+}
+
 // =============================================================================
 // Property Test: Task 1.2 - Property 1
 // Upload-only flag parses correctly with any valid filename
@@ -22,8 +30,8 @@ mod property_upload_only_parsing {
             let args = result.expect("parsing should succeed");
             assert!(args.upload_only, "upload_only should be true");
             assert_eq!(
-                args.filename,
-                Some(PathBuf::from(&filename)),
+                args.filenames,
+                vec![PathBuf::from(&filename)],
                 "filename should match input"
             );
             assert!(!args.init, "init should be false");
@@ -38,8 +46,8 @@ mod property_upload_only_parsing {
             assert!(args.upload_only, "upload_only should be true");
             assert!(args.verbose, "verbose should be true");
             assert_eq!(
-                args.filename,
-                Some(PathBuf::from(&filename)),
+                args.filenames,
+                vec![PathBuf::from(&filename)],
                 "filename should match input"
             );
         }
@@ -66,8 +74,8 @@ mod property_backward_compatibility {
             let args = result.expect("parsing should succeed");
             assert!(!args.upload_only, "upload_only should default to false");
             assert_eq!(
-                args.filename,
-                Some(PathBuf::from(&filename)),
+                args.filenames,
+                vec![PathBuf::from(&filename)],
                 "filename should match input"
             );
             assert!(!args.init, "init should be false");
@@ -83,8 +91,8 @@ mod property_backward_compatibility {
             assert!(!args.upload_only, "upload_only should default to false");
             assert!(args.verbose, "verbose should be true");
             assert_eq!(
-                args.filename,
-                Some(PathBuf::from(&filename)),
+                args.filenames,
+                vec![PathBuf::from(&filename)],
                 "filename should match input"
             );
         }
@@ -96,7 +104,7 @@ mod property_backward_compatibility {
         let args = result.expect("parsing should succeed");
         assert!(!args.upload_only, "upload_only should default to false");
         assert!(args.init, "init should be true");
-        assert_eq!(args.filename, None, "filename should be None with --init");
+        assert!(args.filenames.is_empty(), "filenames should be empty with --init");
     }
 }
 
@@ -137,8 +145,8 @@ mod unit_upload_only_conflicts {
         assert!(args.upload_only, "upload_only should be true");
         assert!(args.verbose, "verbose should be true");
         assert_eq!(
-            args.filename,
-            Some(PathBuf::from("myfile.txt")),
+            args.filenames,
+            vec![PathBuf::from("myfile.txt")],
             "filename should be myfile.txt"
         );
         assert!(!args.init, "init should be false");
@@ -257,3 +265,895 @@ mod property_already_exists_output {
         }
     }
 }
+
+// =============================================================================
+// Unit Tests: Parallel multipart uploads
+// --concurrency flag parsing and validation
+// =============================================================================
+
+mod unit_concurrency_flag {
+    use super::*;
+
+    #[test]
+    fn concurrency_defaults_to_none() {
+        let args = Args::try_parse_from(["shuk", "file.iso"]).expect("parsing should succeed");
+        assert_eq!(args.concurrency, None);
+    }
+
+    #[test]
+    fn concurrency_long_and_short_flags_parse() {
+        let args = Args::try_parse_from(["shuk", "--concurrency", "8", "file.iso"])
+            .expect("--concurrency should parse");
+        assert_eq!(args.concurrency, Some(8));
+
+        let args = Args::try_parse_from(["shuk", "-j", "2", "file.iso"])
+            .expect("-j should parse");
+        assert_eq!(args.concurrency, Some(2));
+    }
+
+    #[test]
+    fn concurrency_of_zero_is_rejected() {
+        let result = Args::try_parse_from(["shuk", "--concurrency", "0", "file.iso"]);
+        assert!(result.is_err(), "--concurrency 0 should produce a clap error");
+    }
+
+    #[test]
+    fn concurrency_with_init_conflicts() {
+        let result = Args::try_parse_from(["shuk", "--init", "--concurrency", "4"]);
+        assert!(result.is_err(), "--concurrency with --init should produce a clap error");
+    }
+}
+
+// =============================================================================
+// Unit Tests: Streaming uploads from stdin
+// `-` as the file name, with the object name as a positional or --name
+// =============================================================================
+
+mod unit_stdin_source {
+    use super::*;
+
+    #[test]
+    fn dash_with_positional_name_reads_stdin() {
+        let args = Args::try_parse_from(["shuk", "-", "backup.sql"])
+            .expect("parsing should succeed");
+        assert!(args.reads_stdin());
+        assert_eq!(args.stdin_object_name(), Some("backup.sql"));
+    }
+
+    #[test]
+    fn dash_with_name_flag_reads_stdin() {
+        let args = Args::try_parse_from(["shuk", "--name", "dir.tar", "-"])
+            .expect("parsing should succeed");
+        assert!(args.reads_stdin());
+        assert_eq!(args.stdin_object_name(), Some("dir.tar"));
+    }
+
+    #[test]
+    fn regular_file_does_not_read_stdin() {
+        let args = Args::try_parse_from(["shuk", "report.pdf"]).expect("parsing should succeed");
+        assert!(!args.reads_stdin());
+        assert_eq!(args.stdin_object_name(), None);
+    }
+
+    #[test]
+    fn name_flag_and_positional_name_conflict() {
+        let args = Args::try_parse_from(["shuk", "--name", "a.txt", "-", "b.txt"])
+            .expect("parsing should succeed");
+        assert!(
+            args.check_sources().is_err(),
+            "--name with a positional name should be rejected"
+        );
+    }
+
+    #[test]
+    fn stdin_without_a_name_is_rejected() {
+        let args = Args::try_parse_from(["shuk", "-"]).expect("parsing should succeed");
+        assert!(args.check_sources().is_err());
+    }
+
+    #[test]
+    fn name_flag_without_stdin_is_rejected() {
+        let args = Args::try_parse_from(["shuk", "--name", "a.txt", "report.pdf"])
+            .expect("parsing should succeed");
+        assert!(args.check_sources().is_err());
+    }
+}
+
+// =============================================================================
+// Unit Tests: Multiple files in a single invocation
+// =============================================================================
+
+mod unit_multiple_files {
+    use super::*;
+
+    #[test]
+    fn several_files_parse_in_order() {
+        let args = Args::try_parse_from(["shuk", "a.txt", "b.pdf", "c.iso"])
+            .expect("parsing should succeed");
+        assert_eq!(
+            args.filenames,
+            vec![
+                PathBuf::from("a.txt"),
+                PathBuf::from("b.pdf"),
+                PathBuf::from("c.iso")
+            ]
+        );
+        assert!(!args.reads_stdin());
+        assert!(args.check_sources().is_ok());
+    }
+
+    #[test]
+    fn stdin_can_not_be_mixed_with_files() {
+        let args = Args::try_parse_from(["shuk", "a.txt", "-"]).expect("parsing should succeed");
+        assert!(args.check_sources().is_err());
+
+        let args = Args::try_parse_from(["shuk", "-", "name.txt", "b.txt"])
+            .expect("parsing should succeed");
+        assert!(args.check_sources().is_err());
+    }
+}
+
+// =============================================================================
+// Unit Tests: Sharing a directory as a single archive
+// =============================================================================
+
+mod unit_archive_flag {
+    use super::*;
+    use shuk::archive::ArchiveFormat;
+
+    #[test]
+    fn archive_formats_parse() {
+        let args = Args::try_parse_from(["shuk", "--archive", "tar.gz", "dist"])
+            .expect("parsing should succeed");
+        assert_eq!(args.archive, Some(ArchiveFormat::TarGz));
+
+        let args = Args::try_parse_from(["shuk", "--archive", "zip", "dist"])
+            .expect("parsing should succeed");
+        assert_eq!(args.archive, Some(ArchiveFormat::Zip));
+    }
+
+    #[test]
+    fn unknown_archive_format_is_rejected() {
+        assert!(Args::try_parse_from(["shuk", "--archive", "rar", "dist"]).is_err());
+    }
+
+    #[test]
+    fn archive_conflicts_with_recursive() {
+        assert!(Args::try_parse_from(["shuk", "--archive", "zip", "-r", "dist"]).is_err());
+    }
+
+    #[test]
+    fn stdin_can_not_be_archived() {
+        let args = Args::try_parse_from(["shuk", "--archive", "zip", "-", "name.zip"])
+            .expect("parsing should succeed");
+        assert!(args.check_sources().is_err());
+    }
+}
+
+// =============================================================================
+// Unit Tests: Client-side encryption flags and the decrypt subcommand
+// =============================================================================
+
+mod unit_encryption {
+    use super::*;
+    use shuk::utils::{Commands, Config};
+
+    fn config() -> Config {
+        config_from_toml(
+            r#"
+encryption_recipients = ["age1configured"]
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn recipient_flags_turn_on_encryption() {
+        let args = Args::try_parse_from([
+            "shuk",
+            "--recipient",
+            "age1first",
+            "--recipient",
+            "age1second",
+            "report.pdf",
+        ])
+        .expect("parsing should succeed");
+        let mut config = config();
+        config.apply_args(&args);
+        assert_eq!(config.encrypt, Some(true));
+        assert_eq!(
+            config.encryption_recipients,
+            Some(vec!["age1first".to_string(), "age1second".to_string()])
+        );
+    }
+
+    #[test]
+    fn passphrase_flag_ignores_configured_recipients() {
+        let args = Args::try_parse_from(["shuk", "--passphrase", "report.pdf"])
+            .expect("parsing should succeed");
+        let mut config = config();
+        config.apply_args(&args);
+        assert_eq!(config.encrypt, Some(true));
+        assert_eq!(config.encryption_recipients, None);
+    }
+
+    #[test]
+    fn passphrase_conflicts_with_recipient() {
+        assert!(Args::try_parse_from([
+            "shuk",
+            "--passphrase",
+            "--recipient",
+            "age1first",
+            "report.pdf"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn decrypt_subcommand_parses() {
+        let args = Args::try_parse_from(["shuk", "decrypt", "report.pdf.age", "-i", "key.txt"])
+            .expect("parsing should succeed");
+        assert!(args.filenames.is_empty());
+        match args.command {
+            Some(Commands::Decrypt {
+                input,
+                output,
+                identity,
+            }) => {
+                assert_eq!(input, PathBuf::from("report.pdf.age"));
+                assert_eq!(output, None);
+                assert_eq!(identity, Some(PathBuf::from("key.txt")));
+            }
+            other => panic!("expected the decrypt subcommand, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decrypt_after_upload_flags_is_a_file_name() {
+        let args = Args::try_parse_from(["shuk", "--encrypt", "decrypt", "report.pdf"])
+            .expect("parsing should succeed");
+        assert!(args.command.is_none());
+        assert_eq!(
+            args.filenames,
+            vec![PathBuf::from("decrypt"), PathBuf::from("report.pdf")]
+        );
+    }
+}
+
+// =============================================================================
+// Unit Tests: Compression flag
+// =============================================================================
+
+mod unit_compress_flag {
+    use super::*;
+    use shuk::compression::Compression;
+    use shuk::utils::Config;
+
+    #[test]
+    fn compress_flag_overrides_the_config() {
+        let mut config: Config = config_from_toml(
+            r#"
+compress = "gzip"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.compress, Some(Compression::Gzip));
+
+        let args = Args::try_parse_from(["shuk", "--compress", "none", "app.log"])
+            .expect("parsing should succeed");
+        config.apply_args(&args);
+        assert_eq!(config.compress, Some(Compression::None));
+    }
+
+    #[test]
+    fn unknown_compression_is_rejected() {
+        assert!(Args::try_parse_from(["shuk", "--compress", "brotli", "app.log"]).is_err());
+    }
+}
+
+// =============================================================================
+// Unit Tests: Checksum flag
+// =============================================================================
+
+mod unit_checksum_flag {
+    use super::*;
+    use shuk::checksum::ChecksumAlgorithm;
+    use shuk::utils::Config;
+
+    #[test]
+    fn checksum_flag_overrides_the_config() {
+        let mut config: Config = config_from_toml(
+            r#"
+checksum_algorithm = "crc32c"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.checksum_algorithm, Some(ChecksumAlgorithm::Crc32c));
+
+        let args = Args::try_parse_from(["shuk", "--checksum", "sha256", "video.mp4"])
+            .expect("parsing should succeed");
+        config.apply_args(&args);
+        assert_eq!(config.checksum_algorithm, Some(ChecksumAlgorithm::Sha256));
+    }
+
+    #[test]
+    fn unsupported_checksum_algorithms_are_rejected() {
+        assert!(Args::try_parse_from(["shuk", "--checksum", "md5", "video.mp4"]).is_err());
+    }
+}
+
+// =============================================================================
+// Unit Tests: Limit rate flag
+// =============================================================================
+
+mod unit_limit_rate_flag {
+    use super::*;
+    use shuk::utils::Config;
+
+    #[test]
+    fn limit_rate_accepts_sizes_and_overrides_the_config() {
+        let mut config: Config = config_from_toml(
+            r#"
+max_bandwidth = "512KB"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.max_bandwidth, Some(512 * 1024));
+
+        let args = Args::try_parse_from(["shuk", "--limit-rate", "2M", "video.mp4"])
+            .expect("parsing should succeed");
+        config.apply_args(&args);
+        assert_eq!(config.max_bandwidth, Some(2 * 1024 * 1024));
+    }
+
+    #[test]
+    fn invalid_rates_are_rejected() {
+        assert!(Args::try_parse_from(["shuk", "--limit-rate", "fast", "video.mp4"]).is_err());
+    }
+}
+
+// =============================================================================
+// Unit Tests: Retry flags
+// =============================================================================
+
+mod unit_retry_flags {
+    use super::*;
+    use shuk::retry::RetryMode;
+    use shuk::utils::Config;
+
+    #[test]
+    fn retry_flags_override_the_config() {
+        let mut config: Config = config_from_toml(
+            r#"
+max_retries = 5
+retry_mode = "adaptive"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.max_retries, Some(5));
+        assert_eq!(config.retry_mode, Some(RetryMode::Adaptive));
+
+        let args = Args::try_parse_from([
+            "shuk",
+            "--retries",
+            "0",
+            "--retry-mode",
+            "standard",
+            "video.mp4",
+        ])
+        .expect("parsing should succeed");
+        config.apply_args(&args);
+        assert_eq!(config.max_retries, Some(0));
+        assert_eq!(config.retry_mode, Some(RetryMode::Standard));
+    }
+
+    #[test]
+    fn unknown_retry_modes_are_rejected() {
+        assert!(Args::try_parse_from(["shuk", "--retry-mode", "legacy", "video.mp4"]).is_err());
+        assert!(Args::try_parse_from(["shuk", "--retries", "-1", "video.mp4"]).is_err());
+    }
+}
+
+// =============================================================================
+// Unit Tests: The uploads subcommand
+// =============================================================================
+
+mod unit_uploads_subcommand {
+    use super::*;
+    use shuk::utils::Commands;
+    use std::time::Duration;
+
+    #[test]
+    fn lists_without_aborting_by_default() {
+        let args = Args::try_parse_from(["shuk", "uploads"]).expect("parsing should succeed");
+        assert!(args.filenames.is_empty());
+        match args.command {
+            Some(Commands::Uploads {
+                keys,
+                older_than,
+                abort,
+                yes,
+            }) => {
+                assert!(keys.is_empty());
+                assert_eq!(older_than, None);
+                assert!(!abort);
+                assert!(!yes);
+            }
+            other => panic!("expected the uploads subcommand, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn aborts_selected_uploads_by_key_and_age() {
+        let args = Args::try_parse_from([
+            "shuk",
+            "uploads",
+            "video.mp4",
+            "--older-than",
+            "7d",
+            "--abort",
+            "-y",
+        ])
+        .expect("parsing should succeed");
+        match args.command {
+            Some(Commands::Uploads {
+                keys,
+                older_than,
+                abort,
+                yes,
+            }) => {
+                assert_eq!(keys, vec!["video.mp4".to_string()]);
+                assert_eq!(older_than, Some(Duration::from_secs(7 * 86400)));
+                assert!(abort);
+                assert!(yes);
+            }
+            other => panic!("expected the uploads subcommand, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn invalid_ages_and_yes_without_abort_are_rejected() {
+        assert!(Args::try_parse_from(["shuk", "uploads", "--older-than", "7"]).is_err());
+        assert!(Args::try_parse_from(["shuk", "uploads", "--older-than", "soon"]).is_err());
+        assert!(Args::try_parse_from(["shuk", "uploads", "--yes"]).is_err());
+    }
+}
+
+// =============================================================================
+// Unit Tests: Content-Type flag
+// =============================================================================
+
+mod unit_content_type_flag {
+    use super::*;
+    use shuk::utils::Config;
+
+    #[test]
+    fn content_type_flag_overrides_detection() {
+        let args = Args::try_parse_from(["shuk", "--content-type", "Text/CSV", "export"])
+            .expect("parsing should succeed");
+        assert_eq!(args.content_type.as_deref(), Some("text/csv"));
+
+        let mut config: Config = config_from_toml("").unwrap();
+        assert_eq!(config.content_type, None);
+        config.apply_args(&args);
+        assert_eq!(config.content_type.as_deref(), Some("text/csv"));
+    }
+
+    #[test]
+    fn parameters_are_kept() {
+        let args = Args::try_parse_from([
+            "shuk",
+            "--content-type",
+            "text/plain; charset=utf-8",
+            "notes",
+        ])
+        .expect("parsing should succeed");
+        assert_eq!(
+            args.content_type.as_deref(),
+            Some("text/plain; charset=utf-8")
+        );
+    }
+
+    #[test]
+    fn invalid_content_types_are_rejected() {
+        assert!(Args::try_parse_from(["shuk", "--content-type", "pdf", "report.pdf"]).is_err());
+        assert!(Args::try_parse_from(["shuk", "--content-type", "", "report.pdf"]).is_err());
+    }
+}
+
+// =============================================================================
+// Unit Tests: Content-Disposition flag
+// =============================================================================
+
+mod unit_content_disposition_flag {
+    use super::*;
+    use shuk::disposition::Disposition;
+    use shuk::utils::Config;
+
+    #[test]
+    fn flag_overrides_the_config() {
+        let mut config: Config = config_from_toml(
+            r#"
+content_disposition = "inline"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.content_disposition, Some(Disposition::Inline));
+
+        let args = Args::try_parse_from(["shuk", "--content-disposition", "attachment", "a.pdf"])
+            .expect("parsing should succeed");
+        config.apply_args(&args);
+        assert_eq!(config.content_disposition, Some(Disposition::Attachment));
+    }
+
+    #[test]
+    fn unknown_modes_are_rejected() {
+        assert!(
+            Args::try_parse_from(["shuk", "--content-disposition", "download", "a.pdf"]).is_err()
+        );
+        assert!(config_from_toml(
+            r#"
+content_disposition = "download"
+"#,
+        )
+        .is_err());
+    }
+}
+
+// =============================================================================
+// Unit Tests: Storage class flag
+// =============================================================================
+
+mod unit_storage_class_flag {
+    use super::*;
+    use shuk::storage_class::StorageClass;
+    use shuk::utils::Config;
+
+    #[test]
+    fn storage_class_comes_from_the_config_or_the_flag() {
+        let mut config: Config = config_from_toml(
+            r#"
+storage_class = "INTELLIGENT_TIERING"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.storage_class, Some(StorageClass::IntelligentTiering));
+
+        let args = Args::try_parse_from(["shuk", "--storage-class", "standard_ia", "backup.tar"])
+            .expect("parsing should succeed");
+        config.apply_args(&args);
+        assert_eq!(config.storage_class, Some(StorageClass::StandardIa));
+    }
+
+    #[test]
+    fn archive_classes_are_rejected() {
+        // A GET of these fails until the object is restored, so nothing could be shared
+        for class in ["GLACIER", "DEEP_ARCHIVE", "REDUCED_REDUNDANCY"] {
+            assert!(
+                Args::try_parse_from(["shuk", "--storage-class", class, "backup.tar"]).is_err(),
+                "{} should be rejected",
+                class
+            );
+        }
+    }
+}
+
+// =============================================================================
+// Unit Tests: Server-side encryption flags
+// =============================================================================
+
+mod unit_sse_flags {
+    use super::*;
+    use shuk::sse::SseMode;
+    use shuk::utils::Config;
+
+    fn config() -> Config {
+        config_from_toml(
+            r#"
+server_side_encryption = "sse-s3"
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn key_flags_imply_their_mode() {
+        let mut kms = config();
+        let args = Args::try_parse_from([
+            "shuk",
+            "--sse-kms-key-id",
+            "alias/shuk",
+            "--sse-bucket-key",
+            "video.mp4",
+        ])
+        .expect("parsing should succeed");
+        kms.apply_args(&args);
+        assert_eq!(kms.server_side_encryption, Some(SseMode::SseKms));
+        assert_eq!(kms.sse_kms_key_id.as_deref(), Some("alias/shuk"));
+        assert_eq!(kms.sse_bucket_key, Some(true));
+
+        let mut customer = config();
+        let args =
+            Args::try_parse_from(["shuk", "--sse-customer-key-file", "sse-c.key", "video.mp4"])
+                .expect("parsing should succeed");
+        customer.apply_args(&args);
+        assert_eq!(customer.server_side_encryption, Some(SseMode::SseC));
+        assert_eq!(
+            customer.sse_customer_key_file,
+            Some(std::path::PathBuf::from("sse-c.key"))
+        );
+    }
+
+    #[test]
+    fn conflicting_or_unknown_modes_are_rejected() {
+        assert!(Args::try_parse_from(["shuk", "--sse", "aes256", "video.mp4"]).is_err());
+        assert!(Args::try_parse_from([
+            "shuk",
+            "--sse-kms-key-id",
+            "alias/shuk",
+            "--sse-customer-key-file",
+            "sse-c.key",
+            "video.mp4"
+        ])
+        .is_err());
+    }
+}
+
+// =============================================================================
+// Unit Tests: Tag and metadata flags
+// =============================================================================
+
+mod unit_tag_and_meta_flags {
+    use super::*;
+    use shuk::utils::Config;
+
+    #[test]
+    fn flags_add_to_the_configured_tables() {
+        let mut config: Config = config_from_toml(
+            r#"
+[tags]
+team = "data"
+project = "shuk"
+
+[metadata]
+owner = "alan"
+"#,
+        )
+        .unwrap();
+        let args = Args::try_parse_from([
+            "shuk",
+            "--tag",
+            "ticket=OPS-42",
+            "--tag",
+            "team=ml",
+            "--meta",
+            "reviewed-by=alan",
+            "report.pdf",
+        ])
+        .expect("parsing should succeed");
+        config.apply_args(&args);
+
+        assert_eq!(config.tags.len(), 3);
+        assert_eq!(config.tags["team"], "ml");
+        assert_eq!(config.tags["ticket"], "OPS-42");
+        assert_eq!(config.metadata["owner"], "alan");
+        assert_eq!(config.metadata["reviewed-by"], "alan");
+    }
+
+    #[test]
+    fn values_without_a_key_are_rejected() {
+        assert!(Args::try_parse_from(["shuk", "--tag", "team", "report.pdf"]).is_err());
+        assert!(Args::try_parse_from(["shuk", "--meta", "=alan", "report.pdf"]).is_err());
+    }
+}
+
+// =============================================================================
+// Unit Tests: Object Lock flags
+// =============================================================================
+
+mod unit_object_lock_flags {
+    use super::*;
+    use shuk::object_lock::LockMode;
+    use shuk::utils::Config;
+
+    #[test]
+    fn flags_override_the_config() {
+        let mut config: Config = config_from_toml(
+            r#"
+object_lock_mode = "GOVERNANCE"
+object_lock_retain_until = "30d"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.object_lock_mode, Some(LockMode::Governance));
+
+        let args = Args::try_parse_from([
+            "shuk",
+            "--object-lock-mode",
+            "compliance",
+            "--retain-until",
+            "2099-12-31",
+            "--legal-hold",
+            "evidence.zip",
+        ])
+        .expect("parsing should succeed");
+        config.apply_args(&args);
+        assert_eq!(config.object_lock_mode, Some(LockMode::Compliance));
+        assert_eq!(
+            config.object_lock_retain_until.as_deref(),
+            Some("2099-12-31")
+        );
+        assert_eq!(config.object_lock_legal_hold, Some(true));
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        assert!(
+            Args::try_parse_from(["shuk", "--retain-until", "someday", "evidence.zip"]).is_err()
+        );
+        assert!(
+            Args::try_parse_from(["shuk", "--object-lock-mode", "STRICT", "evidence.zip"]).is_err()
+        );
+    }
+}
+
+// =============================================================================
+// Unit Tests: Key template flag
+// =============================================================================
+
+mod unit_key_template_flag {
+    use super::*;
+    use shuk::key_template;
+    use shuk::utils::Config;
+
+    fn config() -> Config {
+        config_from_toml(
+            r#"
+key_template = "{user}/{name}"
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn flag_overrides_the_config() {
+        let mut config = config();
+        let args = Args::try_parse_from(["shuk", "--key", "shared/{stem}-v2.{ext}", "report.pdf"])
+            .expect("parsing should succeed");
+        config.apply_args(&args);
+        assert_eq!(
+            config.key_template.as_deref(),
+            Some("shared/{stem}-v2.{ext}")
+        );
+        key_template::check(&config).unwrap();
+        assert_eq!(
+            key_template::object_name(&config, "docs/report.pdf", None).unwrap(),
+            "shared/report-v2.pdf"
+        );
+    }
+
+    #[test]
+    fn unknown_placeholders_are_rejected() {
+        let mut config = config();
+        config.key_template = Some("{team}/{name}".to_string());
+        assert!(key_template::check(&config).is_err());
+    }
+
+    #[test]
+    fn without_a_template_the_name_is_kept() {
+        let mut config = config();
+        config.key_template = None;
+        assert_eq!(
+            key_template::object_name(&config, "docs/report.pdf", None).unwrap(),
+            "docs/report.pdf"
+        );
+    }
+}
+
+// =============================================================================
+// Unit Tests: Unguessable keys
+// =============================================================================
+
+mod unit_unguessable_flag {
+    use super::*;
+    use shuk::unguessable;
+    use shuk::utils::Config;
+
+    fn config() -> Config {
+        config_from_toml("").unwrap()
+    }
+
+    #[test]
+    fn flag_turns_on_random_keys() {
+        let mut config = config();
+        let args = Args::try_parse_from(["shuk", "--unguessable", "report.pdf"])
+            .expect("parsing should succeed");
+        config.apply_args(&args);
+        assert_eq!(config.unguessable_keys, Some(true));
+        unguessable::check(&config).unwrap();
+
+        let key_name = unguessable::object_name(&config, "report.pdf").unwrap();
+        assert_eq!(key_name.len(), 32);
+        assert!(!key_name.contains("report"));
+    }
+
+    #[test]
+    fn flag_conflicts_with_a_key_template() {
+        let result =
+            Args::try_parse_from(["shuk", "--unguessable", "--key", "{name}", "report.pdf"]);
+        assert!(result.is_err());
+
+        let mut config = config();
+        config.unguessable_keys = Some(true);
+        config.key_template = Some("{name}".to_string());
+        assert!(unguessable::check(&config).is_err());
+    }
+}
+
+// =============================================================================
+// Unit Tests: Content addressed keys
+// =============================================================================
+
+mod unit_content_addressed_flag {
+    use super::*;
+    use shuk::content_addressed;
+    use shuk::utils::Config;
+
+    fn config() -> Config {
+        config_from_toml("").unwrap()
+    }
+
+    #[test]
+    fn flag_turns_on_content_addressed_keys() {
+        let mut config = config();
+        let args = Args::try_parse_from(["shuk", "--content-addressed", "ubuntu.iso"])
+            .expect("parsing should succeed");
+        config.apply_args(&args);
+        assert_eq!(config.content_addressed, Some(true));
+        content_addressed::check(&config).unwrap();
+    }
+
+    #[test]
+    fn flag_conflicts_with_other_key_settings() {
+        assert!(Args::try_parse_from([
+            "shuk",
+            "--content-addressed",
+            "--key",
+            "{name}",
+            "ubuntu.iso"
+        ])
+        .is_err());
+        assert!(Args::try_parse_from([
+            "shuk",
+            "--content-addressed",
+            "--unguessable",
+            "ubuntu.iso"
+        ])
+        .is_err());
+    }
+}
+
+// =============================================================================
+// Unit Tests: Collision policy
+// =============================================================================
+
+mod unit_on_conflict_flag {
+    use super::*;
+    use shuk::conflict::OnConflict;
+
+    #[test]
+    fn flag_overrides_the_configured_policy() {
+        let mut config = config_from_toml("on_conflict = \"fail\"").unwrap();
+        assert_eq!(config.on_conflict, Some(OnConflict::Fail));
+        let args = Args::try_parse_from(["shuk", "--on-conflict", "rename", "notes.txt"])
+            .expect("parsing should succeed");
+        config.apply_args(&args);
+        assert_eq!(config.on_conflict, Some(OnConflict::Rename));
+    }
+
+    #[test]
+    fn unknown_policies_are_rejected() {
+        assert!(Args::try_parse_from(["shuk", "--on-conflict", "replace", "notes.txt"]).is_err());
+        assert!(config_from_toml("on_conflict = \"replace\"").is_err());
+    }
+}
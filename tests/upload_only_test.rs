@@ -19,7 +19,7 @@ mod integration_upload_only_cli {
         let args = Args::try_parse_from(["shuk", "somefile.txt"])
             .expect("parsing filename alone should succeed");
         assert!(!args.upload_only, "upload_only should default to false when not provided");
-        assert_eq!(args.filename, Some(PathBuf::from("somefile.txt")));
+        assert_eq!(args.filenames, vec![PathBuf::from("somefile.txt")]);
     }
 
     /// Requirement 1.2: --upload-only with a filename parses correctly
@@ -28,7 +28,7 @@ mod integration_upload_only_cli {
         let args = Args::try_parse_from(["shuk", "--upload-only", "document.pdf"])
             .expect("--upload-only with filename should succeed");
         assert!(args.upload_only, "upload_only should be true");
-        assert_eq!(args.filename, Some(PathBuf::from("document.pdf")));
+        assert_eq!(args.filenames, vec![PathBuf::from("document.pdf")]);
         assert!(!args.init);
         assert!(!args.verbose);
     }
@@ -70,7 +70,7 @@ mod integration_upload_only_cli {
             .expect("--upload-only with --verbose and filename should succeed");
         assert!(args.upload_only, "upload_only should be true");
         assert!(args.verbose, "verbose should be true");
-        assert_eq!(args.filename, Some(PathBuf::from("image.png")));
+        assert_eq!(args.filenames, vec![PathBuf::from("image.png")]);
         assert!(!args.init);
     }
 
@@ -82,7 +82,7 @@ mod integration_upload_only_cli {
         assert!(args.init);
         assert!(!args.upload_only);
         assert!(!args.verbose);
-        assert_eq!(args.filename, None);
+        assert!(args.filenames.is_empty());
     }
 
     /// Requirement 6.5: filename with --verbose still works (backward compatibility)
@@ -92,7 +92,7 @@ mod integration_upload_only_cli {
             .expect("filename with --verbose should still work");
         assert!(!args.upload_only, "upload_only should default to false");
         assert!(args.verbose);
-        assert_eq!(args.filename, Some(PathBuf::from("backup.tar.gz")));
+        assert_eq!(args.filenames, vec![PathBuf::from("backup.tar.gz")]);
     }
 
     /// Requirement 6.1, 6.2, 6.3: Default mode (no --upload-only) produces upload_only == false
@@ -102,7 +102,7 @@ mod integration_upload_only_cli {
         let args = Args::try_parse_from(["shuk", "report.csv"])
             .expect("default mode should succeed");
         assert!(!args.upload_only, "upload_only must be false in default mode");
-        assert_eq!(args.filename, Some(PathBuf::from("report.csv")));
+        assert_eq!(args.filenames, vec![PathBuf::from("report.csv")]);
     }
 
     /// Various valid filenames with --upload-only
@@ -120,7 +120,7 @@ mod integration_upload_only_cli {
             let args = Args::try_parse_from(["shuk", "--upload-only", filename])
                 .unwrap_or_else(|_| panic!("--upload-only with '{}' should succeed", filename));
             assert!(args.upload_only);
-            assert_eq!(args.filename, Some(PathBuf::from(filename)));
+            assert_eq!(args.filenames, vec![PathBuf::from(filename)]);
         }
     }
 }